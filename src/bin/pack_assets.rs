@@ -0,0 +1,74 @@
+// Packs the game's assets and data files into one archive the game can load
+// instead of loose files, or embed with `--features embed-assets`.
+//
+//   cargo run --bin pack_assets [OUTPUT]
+//
+// packs `src/res` and `data` into `assets.pak` by default.
+
+#[allow(dead_code)]
+#[path = "../game/pack.rs"]
+mod pack;
+
+use std::borrow::Cow;
+use std::fs;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::process;
+
+
+// Directories packed and the prefix their files get inside the archive, matching
+// the names the game asks for, e.g. "textures/ground.png" and "data/waves.toml".
+const SOURCES: &[(&str, &str)] = &[("src/res", ""), ("data", "data/")];
+
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let output = args.next().unwrap_or_else(|| "assets.pak".to_string());
+    if args.next().is_some() {
+        eprintln!("usage: pack_assets [OUTPUT]");
+        process::exit(2);
+    }
+
+    let mut files = Vec::new();
+    for (source, prefix) in SOURCES {
+        if let Err(err) = collect(Path::new(source), prefix, &mut files) {
+            eprintln!("{}: {}", source, err);
+            process::exit(1);
+        }
+    }
+    files.sort();
+    if let Err(err) = pack(&files, Path::new(&output)) {
+        eprintln!("{}: {}", output, err);
+        process::exit(1);
+    }
+}
+
+fn pack(files: &[(String, Vec<u8>)], output: &Path) -> io::Result<()> {
+    let mut out = BufWriter::new(fs::File::create(output)?);
+    pack::write_archive(&mut out, files)?;
+    drop(out);
+
+    // Read it back so a broken archive never ships.
+    let archive = pack::Archive::parse(Cow::Owned(fs::read(output)?))?;
+    let size: usize = files.iter().map(|(_, data)| data.len()).sum();
+    println!("packed {} files ({} bytes) into {}", archive.names().count(), size, output.display());
+    Ok(())
+}
+
+fn collect(dir: &Path, prefix: &str, files: &mut Vec<(String, Vec<u8>)>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        // Hidden files and notes like README.md stay out; every other file ships,
+        // extension or not.
+        let name = match path.file_name().and_then(|n| n.to_str()) {
+            Some(file_name) if !file_name.starts_with('.') => format!("{}{}", prefix, file_name),
+            _ => continue,
+        };
+        if path.is_dir() {
+            collect(&path, &format!("{}/", name), files)?;
+        } else if path.extension().is_none_or(|ext| ext != "md") {
+            files.push((name, fs::read(&path)?));
+        }
+    }
+    Ok(())
+}
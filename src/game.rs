@@ -1,302 +1,606 @@
 extern crate sfml;
 extern crate rand;
+extern crate serde;
+extern crate toml;
 mod state_machine;
-// mod texture_manager;
+mod texture_manager;
 mod entity;
 mod ui;
 mod cards;
+mod world;
+mod timer;
+mod scene;
+mod handle;
+mod events;
+mod archetype;
+mod watcher;
+mod waves;
+mod camera;
+mod animation;
+mod hud;
+mod profile;
+mod deck_builder;
+mod assets;
+mod pack;
+mod font_manager;
 
-use entity::{Damageable, Entity, HumanState, EnemyState};
 use sfml::{graphics, window, system};
-use std::option::Option::Some;
-use self::sfml::graphics::{RenderTarget, Transformable, Color};
-use crate::settings::{WINDOW_WIDTH, GROUND_POS_Y, WINDOW_HEIGHT};
-use crate::game::entity::{Building, BuildingType};
-use crate::game::ui::{DrawHP, GeoInfo};
-use self::sfml::system::Vector2f;
-use std::collections::{HashSet, HashMap};
-use std::intrinsics::transmute;
-use std::ops::Index;
-
-
-#[derive(Clone, Debug)]
+use self::sfml::graphics::{RenderTarget, Color};
+use crate::settings::{Settings, CommandLine, CONFIG_PATH};
+use crate::game::ui::{DrawHP, UIString, UICardsInfo, Ui, WidgetId, Direction, PanelStyle, Align};
+use crate::game::world::World;
+use crate::game::scene::{Scene, SceneStack, MenuAction};
+use crate::game::events::BattleStats;
+use crate::game::archetype::{UnitRegistry, DataError};
+use crate::game::watcher::FileWatcher;
+use crate::game::waves::WavePlan;
+use crate::game::cards::{CardLibrary, CardEffect};
+use crate::game::camera::Camera;
+use crate::game::hud::Hud;
+use crate::game::profile::{Profile, ProfileError};
+use crate::game::deck_builder::{DeckBuilder, DeckAction};
+pub use crate::game::texture_manager::TextureManager;
+pub use crate::game::font_manager::FontManager;
+pub use crate::game::assets::{AssetLocator, AssetError};
+use self::sfml::system::{Vector2f, Vector2i, SfBox};
+use std::cell::RefCell;
+use std::rc::Rc;
+use self::serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
 enum EnemyComing {
     RIGHT,
     LEFT,
 }
 
 
+// Why the game could not start.
+#[derive(Debug)]
+pub enum GameError {
+    Asset(AssetError),
+    Data(DataError),
+    Profile(ProfileError),
+}
+
+impl std::fmt::Display for GameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GameError::Asset(err) => write!(f, "{}", err),
+            GameError::Data(err) => write!(f, "{}", err),
+            GameError::Profile(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+
 pub struct Game<'a> {
     win: graphics::RenderWindow,
-    humans: Vec<Box<entity::Human<'a>>>,
-    humans_pos_list: HashMap<u32, Vector2f>,
-    enemies: Vec<Box<entity::Enemy<'a>>>,
-    buildings: Vec<Box<entity::Building<'a>>>,
-    // buildings_pos_list: HashMap<u32, Vector2f>,
-    buildings_pos_list: Vec<Vector2f>,
-    base_ground: Vec<entity::BaseGround<'static>>,
-    enemies_pos_list: HashMap<u32, Vector2f>,
+    world: World<'a>,
+    stats: Rc<RefCell<BattleStats>>,
+    settings: Settings,
+    cli: CommandLine,
+    units: UnitRegistry,
+    waves: WavePlan,
+    cards: CardLibrary,
+    profile: Profile,
+    // Card unlocked by the last victory, for its summary.
+    unlocked_card: Option<String>,
+    watcher: FileWatcher,
+    // The last reload or battle start that failed.
+    error_banner: Option<UIString<'a>>,
+    scenes: SceneStack,
+    camera: Camera,
+    ui_view: SfBox<graphics::View>,
+    font: &'a graphics::Font,
+    // Widgets of the menu for the scene on top of the stack, rebuilt when it changes.
+    menu: Ui<'a, MenuAction>,
+    menu_scene: Option<Scene>,
+    menu_buttons: Vec<WidgetId>,
+    hud: Hud<'a>,
+    hand_ui: UICardsInfo<'a>,
+    deck_builder: DeckBuilder<'a>,
+    assets: &'a AssetLocator,
+    textures: &'a TextureManager,
+    base_ground: Vec<entity::BaseGround<'a>>,
     clock: system::Clock,
-    is_paused: bool,
-    is_game_over: bool,
-    attacked_human_list: Vec<Box<AttackInfo>>,
-    attacked_human_ids: HashSet<u32>,
-    attacked_enemy_list: Vec<Box<AttackInfo>>,
-    attacked_enemy_ids: HashSet<u32>,
-    enemy_coming: Option<EnemyComing>,
+    accumulator: f32,
+    game_speed_index: usize,
 }
 
-impl Game<'_> {
+impl<'a> Game<'a> {
 
-    pub fn new(width: u32, height: u32, title: &str) -> Game {
-        let win = graphics::RenderWindow::new(window::VideoMode::new(width, height,
+    pub fn new(settings: Settings, cli: CommandLine, assets: &'a AssetLocator, textures: &'a TextureManager,
+               fonts: &'a FontManager) -> Result<Game<'a>, GameError> {
+        let font = fonts.require(&settings.ui_font).map_err(GameError::Asset)?;
+        let (units, waves, cards) = load_data(&settings, assets).map_err(GameError::Data)?;
+        let win = graphics::RenderWindow::new(window::VideoMode::new(settings.width, settings.height,
                                                                     window::VideoMode::desktop_mode().bits_per_pixel),
-                                            title, window::Style::default(), &window::ContextSettings::default());
-
-        let mut humans_pos_list = HashMap::new();
-        let mut enemies_pos_list = HashMap::new();
-        let mut humans = vec![Box::new(entity::Human::new(0))];
-        let mut h = entity::Human::new(1);
-        h.image.set_position(Vector2f::new(300.0, GROUND_POS_Y));
-        humans.push(Box::new(h));
-        let enemies = vec![Box::new(entity::Enemy::new(0))];
-        for human in humans.iter() {
-            humans_pos_list.insert(human.get_id(), human.get_position());
-        }
-        for enemy in enemies.iter() {
-            enemies_pos_list.insert(enemy.get_id(), enemy.get_position());
-        }
-
-        let mut buildings_pos_list = Vec::new();
-        let mut buildings = vec![Box::new(entity::Building::new(BuildingType::Base))];
-        for building in buildings.iter() {
-            buildings_pos_list.push(building.get_position());
-        }
-
-        let base_ground = vec![entity::BaseGround::new()];
-        Game {
+                                            &settings.title, window::Style::default(), &window::ContextSettings::default());
+        // A broken profile stops the game rather than being replaced, since the
+        // next save would overwrite the player's unlocks and decks.
+        let profile = Profile::load(Path::new(&settings.profile_file), &cards).map_err(GameError::Profile)?;
+        let deck = profile.battle_deck(&cards);
+
+        let base_ground = vec![entity::BaseGround::new(&settings, textures)];
+        let window_size = Vector2f::new(settings.width as f32, settings.height as f32);
+        let mut game = Game {
             win,
-            humans,
-            humans_pos_list,
-            enemies,
-            buildings,
-            buildings_pos_list,
+            world: World::new(&settings, units.clone(), waves.clone(), cards.clone(), &deck, textures)
+                .map_err(GameError::Data)?,
+            stats: Rc::new(RefCell::new(BattleStats::default())),
+            game_speed_index: settings.default_game_speed_index,
+            camera: Camera::new(window_size, settings.world_width),
+            watcher: watch_data_files(&settings, &cli, assets),
+            error_banner: None,
+            settings,
+            cli,
+            units,
+            waves,
+            cards,
+            profile,
+            unlocked_card: None,
+            scenes: SceneStack::new(Scene::MainMenu),
+            ui_view: graphics::View::new(window_size / 2.0, window_size),
+            font,
+            menu: Ui::new(font),
+            menu_scene: None,
+            menu_buttons: Vec::new(),
+            hud: Hud::new(font),
+            hand_ui: UICardsInfo::new(font, window_size),
+            deck_builder: DeckBuilder::new(font, window_size),
+            assets,
+            textures,
             base_ground,
-            enemies_pos_list,
             clock: system::Clock::default(),
-            is_paused: false,
-            is_game_over: false,
-            attacked_human_list: Vec::new(),
-            attacked_human_ids: HashSet::new(),
-            attacked_enemy_list: Vec::new(),
-            attacked_enemy_ids: HashSet::new(),
-            enemy_coming: Some(EnemyComing::RIGHT),
+            accumulator: 0.0,
+        };
+        game.battle_started();
+        Ok(game)
+    }
+
+    // Leaves the current battle alone if the new one cannot be set up.
+    fn start_battle(&mut self) -> Result<(), DataError> {
+        let deck = self.profile.battle_deck(&self.cards);
+        self.world = World::new(&self.settings, self.units.clone(), self.waves.clone(), self.cards.clone(), &deck,
+                                self.textures)?;
+        self.battle_started();
+        Ok(())
+    }
+
+    // Resets everything around a freshly built world.
+    fn battle_started(&mut self) {
+        self.hud.set_notice(None);
+        self.hand_ui.cancel();
+        self.unlocked_card = None;
+        self.stats = Rc::new(RefCell::new(BattleStats::default()));
+        let stats = Rc::clone(&self.stats);
+        self.world.subscribe(move |event| stats.borrow_mut().record(event));
+        self.accumulator = 0.0;
+        self.camera = Camera::new(self.camera_size(), self.settings.world_width);
+    }
+
+    fn show_error(&mut self, message: &str) {
+        let mut banner = UIString::new(message, self.font, 16, Color::RED);
+        banner.set_position(Vector2f::new(10.0, self.settings.height as f32 - 30.0));
+        self.error_banner = Some(banner);
+    }
+
+    fn camera_size(&self) -> Vector2f {
+        Vector2f::new(self.settings.width as f32, self.settings.height as f32)
+    }
+
+    // Re-reads the config, unit and card files and applies them to the running battle;
+    // wave changes take effect from the next battle. On failure the old values stay
+    // in place and the error is shown on screen.
+    fn reload(&mut self) {
+        let reloaded = Settings::load(&self.cli).map_err(|err| err.to_string())
+            .and_then(|settings| {
+                let units = UnitRegistry::load(self.assets, &settings.units_data_dir).map_err(|err| err.to_string())?;
+                let waves = WavePlan::load(self.assets, &settings.waves_file, &units).map_err(|err| err.to_string())?;
+                let cards = CardLibrary::load(self.assets, &settings.cards_file, &units).map_err(|err| err.to_string())?;
+                // The battle keeps spawning from the plan it started with, so that
+                // plan has to work with the new units too.
+                self.world.wave_plan().validate_waves(Path::new(&self.settings.waves_file), &units)
+                    .map_err(|err| format!("the running battle's waves: {}", err))?;
+                Ok((settings, units, waves, cards))
+            });
+        let (settings, units, waves, cards) = match reloaded {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                self.show_error(&format!("Reload failed: {}", err));
+                return;
+            },
+        };
+
+        // The window is already open, so its size and title only change on the next launch.
+        self.settings = Settings {
+            width: self.settings.width,
+            height: self.settings.height,
+            title: self.settings.title.clone(),
+            ..settings
+        };
+        self.game_speed_index = self.game_speed_index.min(self.settings.game_speeds.len() - 1);
+        self.base_ground = vec![entity::BaseGround::new(&self.settings, self.textures)];
+        self.camera.set_world_width(self.settings.world_width);
+        self.world.apply_settings(&self.settings, units.clone(), cards.clone());
+        self.profile.reconcile(&cards);
+        if self.scenes.top() == Scene::DeckBuilder {
+            self.deck_builder.rebuild(&self.profile, &cards);
         }
+        self.units = units;
+        self.waves = waves;
+        self.cards = cards;
+        self.watcher = watch_data_files(&self.settings, &self.cli, self.assets);
+        self.error_banner = None;
     }
 
     fn events(&mut self) {
         while let Some(event) = self.win.poll_event() {
             match event {
                 window::Event::Closed => self.win.close(),
-                window::Event::KeyPressed {code: window::Key::Escape, ..} => self.win.close(),
-                window::Event::KeyPressed {code: window::Key::F9, ..} => self.is_paused = !self.is_paused,
+                window::Event::KeyPressed {code, ..} => self.key_pressed(code),
+                window::Event::TextEntered {unicode} if self.scenes.top() == Scene::DeckBuilder => {
+                    self.deck_builder.text_entered(unicode, &mut self.profile, &self.cards);
+                },
+                // Right clicking puts a dragged card back instead of grabbing the camera.
+                window::Event::MouseButtonPressed {button: window::mouse::Button::Right, ..}
+                    if self.hand_ui.dragging().is_some() => {
+                    self.hand_ui.cancel();
+                },
+                window::Event::MouseButtonPressed {button: window::mouse::Button::Middle, x, ..}
+                | window::Event::MouseButtonPressed {button: window::mouse::Button::Right, x, ..}
+                    if self.scenes.top() == Scene::Playing => self.camera.start_drag(x),
+                window::Event::MouseButtonReleased {button: window::mouse::Button::Middle, ..}
+                | window::Event::MouseButtonReleased {button: window::mouse::Button::Right, ..} => {
+                    self.camera.end_drag();
+                },
+                window::Event::MouseButtonPressed {button: window::mouse::Button::Left, x, y}
+                    if self.scenes.top() == Scene::Playing => {
+                    let pos = self.ui_coords(x, y);
+                    self.hand_ui.mouse_pressed(pos, self.world.gold());
+                },
+                window::Event::MouseButtonPressed {button: window::mouse::Button::Left, x, y}
+                    if self.scenes.top() == Scene::DeckBuilder => {
+                    let pos = self.ui_coords(x, y);
+                    if let Some(action) = self.deck_builder.click(pos) {
+                        self.deck_action(action);
+                    }
+                },
+                window::Event::MouseButtonPressed {button: window::mouse::Button::Left, x, y} => {
+                    self.sync_menu();
+                    let pos = self.ui_coords(x, y);
+                    if let Some(action) = self.menu.click(pos) {
+                        self.menu_action(action);
+                    }
+                },
+                window::Event::MouseButtonReleased {button: window::mouse::Button::Left, x, y} => {
+                    let pos = self.ui_coords(x, y);
+                    if let Some(index) = self.hand_ui.mouse_released(pos) {
+                        let target = self.win.map_pixel_to_coords(Vector2i::new(x, y), &self.camera.view());
+                        self.play_card(index, target.x);
+                    }
+                },
+                window::Event::MouseMoved {x, y} => {
+                    self.camera.mouse_moved(Some(x));
+                    let pos = self.ui_coords(x, y);
+                    self.hand_ui.mouse_moved(pos);
+                    self.deck_builder.mouse_moved(pos);
+                    self.menu_hover(x, y);
+                },
+                window::Event::MouseLeft => self.camera.mouse_moved(None),
                 _ => {},
             }
         }
     }
 
-    fn update_and_draw(&mut self, dt: f32) {
-        self.win.clear(graphics::Color::BLACK);
-
-        let mut player_team_remove_index = Vec::new();
-        let mut enemy_team_remove_index = Vec::new();
+    fn ui_coords(&self, x: i32, y: i32) -> Vector2f {
+        self.win.map_pixel_to_coords(Vector2i::new(x, y), &self.ui_view)
+    }
 
-        // Drawing
-        for base_ground in &self.base_ground {
-            self.win.draw(&base_ground.image);
+    // Pointing at a menu button selects it, just like the arrow keys.
+    fn menu_hover(&mut self, x: i32, y: i32) {
+        self.sync_menu();
+        let pos = self.ui_coords(x, y);
+        if let Some(hovered) = self.menu.mouse_moved(pos) {
+            if let Some(index) = self.menu_buttons.iter().position(|button| *button == hovered) {
+                self.scenes.select(index);
+            }
         }
-        for building in &mut self.buildings {
-            if self.attacked_human_ids.contains(&building.get_id()) {
-                let mut drop_index = Vec::new();
-                for (index, item_box) in self.attacked_human_list.iter().enumerate() {
-                    let id = item_box.attacked_id;
-                    let dmg = item_box.dmg_taken;
-                    if building.get_id() != id {
-                        continue;
-                    } else {
-                        building.set_hp(building.get_hp() - dmg);
-                        drop_index.push(index);
-                    }
-                }
+    }
 
-                self.attacked_human_ids.remove(&building.get_id());
-                while drop_index.len() > 0 {
-                    self.attacked_human_list.remove(drop_index[0]);
-                    drop_index.remove(0);
-                    drop_index.iter().map(|x| x - 1);
+    fn key_pressed(&mut self, code: window::Key) {
+        match (self.scenes.top(), code) {
+            (Scene::Playing, window::Key::Escape) if self.hand_ui.dragging().is_some() => {
+                self.hand_ui.cancel();
+            },
+            (Scene::Playing, window::Key::Escape) | (Scene::Playing, window::Key::F9) => {
+                self.hand_ui.cancel();
+                self.scenes.push(Scene::Paused);
+            },
+            (Scene::Playing, window::Key::F10) => {
+                self.game_speed_index = self.game_speed_index.saturating_sub(1);
+            },
+            (Scene::Playing, window::Key::F11) => {
+                self.game_speed_index = (self.game_speed_index + 1).min(self.settings.game_speeds.len() - 1);
+            },
+            // Shortcuts that play a card in the middle of the view without dragging it there.
+            (Scene::Playing, window::Key::Num1) => self.play_card(0, self.camera.center_x()),
+            (Scene::Playing, window::Key::Num2) => self.play_card(1, self.camera.center_x()),
+            (Scene::Playing, window::Key::Num3) => self.play_card(2, self.camera.center_x()),
+            (Scene::Playing, window::Key::Num4) => self.play_card(3, self.camera.center_x()),
+            (Scene::Playing, window::Key::Num5) => self.play_card(4, self.camera.center_x()),
+            (Scene::Paused, window::Key::Escape) | (Scene::Paused, window::Key::F9) => self.scenes.pop(),
+            (Scene::MainMenu, window::Key::Escape) => self.win.close(),
+            (Scene::DeckBuilder, window::Key::Escape) if self.deck_builder.is_renaming() => {
+                self.deck_builder.cancel_rename(&self.profile, &self.cards);
+            },
+            (Scene::DeckBuilder, window::Key::Escape) => self.deck_action(DeckAction::Back),
+            (_, window::Key::Up) => self.scenes.select_prev(),
+            (_, window::Key::Down) => self.scenes.select_next(),
+            (_, window::Key::Return) => {
+                if let Some(action) = self.scenes.selected_action() {
+                    self.menu_action(action);
                 }
-            }
-            self.win.draw(&building.image);
-            building.draw_hp(&mut self.win);
+            },
+            _ => {},
         }
+    }
 
-        self.attacked_human_list.clear();
-        self.attacked_human_ids.clear();
+    fn play_card(&mut self, index: usize, x: f32) {
+        match self.world.play_card(index, x) {
+            Ok(()) => self.hud.set_notice(None),
+            Err(err) => self.hud.set_notice(Some(&err.to_string())),
+        }
+    }
 
-        for enemy in &mut self.enemies {
-            if enemy.get_hp() <= 0.0 {
-                enemy_team_remove_index.push(enemy.get_id());
-                continue;
-            }
-            if self.attacked_enemy_ids.contains(&enemy.get_id()) {
-                let mut drop_index = Vec::new();
-                for (index, item_box) in self.attacked_enemy_list.iter().enumerate() {
-                    let id = item_box.attacked_id;
-                    let dmg = item_box.dmg_taken;
-                    if enemy.get_id() != id {
-                        continue;
-                    } else {
-                        enemy.set_hp(enemy.get_hp() - dmg);
-                        drop_index.push(index);
-                    }
-                }
+    fn menu_action(&mut self, action: MenuAction) {
+        match action {
+            MenuAction::Start | MenuAction::Restart => match self.start_battle() {
+                Ok(()) => self.scenes.reset(Scene::Playing),
+                Err(err) => self.show_error(&format!("Cannot start the battle: {}", err)),
+            },
+            MenuAction::Decks => {
+                self.deck_builder.rebuild(&self.profile, &self.cards);
+                self.scenes.push(Scene::DeckBuilder);
+            },
+            MenuAction::Resume => self.scenes.pop(),
+            MenuAction::QuitToMenu => self.scenes.reset(Scene::MainMenu),
+            MenuAction::Quit => self.win.close(),
+        }
+    }
 
-                self.attacked_enemy_ids.remove(&enemy.get_id());
-                while drop_index.len() > 0 {
-                    self.attacked_enemy_list.remove(drop_index[0]);
-                    drop_index.remove(0);
-                    drop_index.iter_mut().map(|x| *x - 1);
-                }
-            }
+    // The profile is saved when the player leaves the deck builder.
+    fn deck_action(&mut self, action: DeckAction) {
+        if self.deck_builder.apply(action, &mut self.profile, &self.cards) {
+            self.save_profile();
+            self.scenes.pop();
+        }
+    }
 
-            enemy.update_building_pos_list(self.buildings_pos_list.clone());
-            enemy.update(dt, &mut self.enemies_pos_list, &Some(EnemyComing::LEFT), &self.humans_pos_list);
-            if let Some((attacked_human_id, dmg)) = enemy.get_attack_target() {
-                self.attacked_human_ids.insert(attacked_human_id);
-                self.attacked_human_list.push(Box::new(AttackInfo {attacked_id: attacked_human_id,
-                                                                dmg_taken: dmg}));
-            }
-            self.win.draw(&enemy.image);
-            enemy.draw_hp(&mut self.win);
+    fn save_profile(&self) {
+        if let Err(err) = self.profile.save(Path::new(&self.settings.profile_file)) {
+            eprintln!("Error saving profile: {}", err);
         }
+    }
 
-        for human in &mut self.humans {
-            if human.get_hp() <= 0.0 {
-                player_team_remove_index.push(human.get_id());
-                continue;
+    fn update(&mut self, frame_time: f32) {
+        if self.scenes.top() != Scene::Playing {
+            return;
+        }
+        self.update_camera(frame_time);
+        let timestep = self.settings.fixed_timestep;
+        self.accumulator += frame_time * self.settings.game_speeds[self.game_speed_index];
+        while self.accumulator >= timestep {
+            self.world.step(timestep);
+            self.accumulator -= timestep;
+        }
+        if self.world.is_game_over() {
+            self.scenes.push(Scene::GameOver);
+        } else if self.world.is_victory() {
+            self.unlocked_card = self.profile.unlock_next(&self.cards);
+            if self.unlocked_card.is_some() {
+                self.save_profile();
             }
-            if self.attacked_human_ids.contains(&human.get_id()) {
-                let mut drop_index = Vec::new();
-                for (index, item_box) in self.attacked_human_list.iter().enumerate() {
-                    let id = item_box.attacked_id;
-                    let dmg = item_box.dmg_taken;
-                    if human.get_id() != id {
-                        continue;
-                    } else {
-                        human.set_hp(human.get_hp() - dmg);
-                        drop_index.push(index);
-                    }
-                }
+            self.scenes.push(Scene::Victory);
+        }
+    }
 
-                self.attacked_human_ids.remove(&human.get_id());
-                while drop_index.len() > 0 {
-                    self.attacked_human_list.remove(drop_index[0]);
-                    drop_index.remove(0);
-                    drop_index.iter_mut().map(|x| *x - 1);
-                }
+    // Arrow keys or A/D pan in wall time, so the speed setting does not affect scrolling.
+    fn update_camera(&mut self, frame_time: f32) {
+        let mut direction = 0.0;
+        if self.win.has_focus() {
+            if window::Key::Left.is_pressed() || window::Key::A.is_pressed() {
+                direction -= 1.0;
             }
-
-            human.update(dt, &mut self.humans_pos_list, &self.enemy_coming, &self.enemies_pos_list);
-            if let Some((attacked_enemy_id, dmg)) = human.get_attack_target() {
-                self.attacked_enemy_ids.insert(attacked_enemy_id);
-                self.attacked_enemy_list.push(Box::new(AttackInfo {attacked_id: attacked_enemy_id,
-                    dmg_taken: dmg}));
+            if window::Key::Right.is_pressed() || window::Key::D.is_pressed() {
+                direction += 1.0;
             }
+        }
+        self.camera.update(frame_time, direction, self.settings.camera_pan_speed, self.settings.camera_edge_margin);
+    }
+
+    fn draw(&mut self) {
+        self.win.clear(graphics::Color::BLACK);
+        if self.scenes.visible().contains(&Scene::Playing) {
+            self.draw_world();
+            let speed = self.settings.game_speeds[self.game_speed_index];
+            self.hud.update(&self.world, &self.settings, speed);
+            self.hud.draw(&mut self.win);
+            self.hand_ui.sync(self.world.cards(), self.world.gold());
+            self.hand_ui.draw(&mut self.win);
+        }
+        if self.scenes.top() == Scene::DeckBuilder {
+            self.deck_builder.draw(&mut self.win);
+        }
+        self.sync_menu();
+        self.menu.draw(&mut self.win);
+        if let Some(banner) = &self.error_banner {
+            banner.draw(&mut self.win);
+        }
+        self.win.display();
+    }
+
+    fn draw_world(&mut self) {
+        self.win.set_view(&self.camera.view());
+        for base_ground in &self.base_ground {
+            self.win.draw(&base_ground.image);
+        }
+        for building in self.world.buildings() {
+            self.win.draw(&building.image);
+            building.draw_hp(&mut self.win);
+        }
+        for enemy in self.world.enemies() {
+            self.win.draw(&enemy.image);
+            enemy.draw_hp(&mut self.win);
+        }
+        for human in self.world.humans() {
             human.draw_hp(&mut self.win);
             self.win.draw(&human.image);
         }
+        self.draw_placement_preview();
+        self.win.set_view(&self.ui_view);
+    }
 
-        let mut humans_drop_index = Vec::new();
-        for player_id in player_team_remove_index.iter() {
-            let mut temp_list = self.humans_pos_list.clone();
-            for (id, _) in self.humans_pos_list.iter() {
-                if *id != *player_id { continue; }
-                temp_list.remove(id);
-            }
-            self.humans_pos_list = temp_list;
-            for (index, item) in self.humans.iter().enumerate() {
-                if *player_id == item.get_id() {
-                    humans_drop_index.push(index);
-                }
-            }
-        }
-        while humans_drop_index.len() > 0 {
-            self.humans.remove(humans_drop_index[0]);
-            humans_drop_index.remove(0);
-            humans_drop_index.iter_mut().map(|x| *x - 1);
+    // Shows where the dragged card would land while it is over the battlefield:
+    // green for what it puts on the ground, red for a building that does not fit
+    // there, orange or blue for a spell's reach.
+    fn draw_placement_preview(&mut self) {
+        let index = match self.hand_ui.dragging() {
+            Some(index) => index,
+            None => return,
+        };
+        let pointer = self.hand_ui.pointer();
+        if self.hand_ui.is_over_hand(pointer) {
+            return;
         }
+        let card = match self.world.cards().hand_card(index) {
+            Some(card) => card,
+            None => return,
+        };
+        let size = card.effect.footprint(&self.units, &self.settings);
+        let pixel = self.win.map_coords_to_pixel(pointer, &self.ui_view);
+        let x = self.win.map_pixel_to_coords(pixel, &self.camera.view()).x.clamp(0.0, self.settings.world_width);
+        let (color, texture) = match card.effect {
+            CardEffect::Fireball { .. } => (Color::rgb(255, 140, 0), None),
+            CardEffect::Heal { .. } => (Color::rgb(80, 160, 255), None),
+            CardEffect::Spawn { .. } => (Color::GREEN, None),
+            // A ghost of the building, red where it cannot be placed.
+            CardEffect::Build { building } => {
+                let color = match self.world.check_building_placement(building, x) {
+                    Ok(()) => Color::GREEN,
+                    Err(_) => Color::RED,
+                };
+                (color, self.textures.get(building.sprite()))
+            },
+        };
+        let pos = Vector2f::new(x, self.settings.ground_pos_y);
+        self.hand_ui.draw_preview(&mut self.win, pos, size, color, texture);
+    }
 
-        let mut enemies_drop_index = Vec::new();
-        for enemy_id in enemy_team_remove_index.iter() {
-            let mut temp_list = self.enemies_pos_list.clone();
-            for (id, _) in self.enemies_pos_list.iter() {
-                if *id != *enemy_id { continue; }
-                temp_list.remove(id);
-            }
-            self.enemies_pos_list = temp_list;
-            for (index, item) in self.enemies.iter().enumerate() {
-                if *enemy_id == item.get_id() {
-                    enemies_drop_index.push(index);
-                }
-            }
+    fn sync_menu(&mut self) {
+        let top = self.scenes.top();
+        if self.menu_scene != Some(top) {
+            self.build_menu(top);
         }
-        while enemies_drop_index.len() > 0 {
-            self.enemies.remove(enemies_drop_index[0]);
-            enemies_drop_index.remove(0);
-            enemies_drop_index.iter_mut().map(|x| *x - 1);
+        for (index, button) in self.menu_buttons.iter().enumerate() {
+            self.menu.set_highlighted(*button, index == self.scenes.selected());
         }
+    }
 
-        self.game_over_update();
+    fn build_menu(&mut self, scene: Scene) {
+        let (width, height) = (self.settings.width as f32, self.settings.height as f32);
+        let mut menu = Ui::new(self.font);
+        let mut buttons = Vec::new();
 
-        if self.enemies.len() < 1 {
-            self.enemy_coming = None;
+        if scene.is_overlay() {
+            menu.image(None, None, Vector2f::new(width, height), Color::rgba(0, 0, 0, 160));
+        }
+        if !scene.menu().is_empty() {
+            let style = PanelStyle { spacing: 16.0, align: Align::Center, ..PanelStyle::default() };
+            let column = menu.panel(None, Direction::Vertical, style);
+            menu.set_anchor(column, Vector2f::new(width / 2.0, height / 2.0), Vector2f::new(0.5, 0.5));
+            menu.label(Some(column), scene.title(), 40, Color::WHITE);
+            if scene == Scene::GameOver || scene == Scene::Victory {
+                let stats = self.stats.borrow();
+                let summary = format!("Enemies defeated: {}   Units lost: {}   Leaked: {}   Damage dealt: {:.0}",
+                                      stats.enemies_defeated, stats.units_lost, stats.enemies_leaked,
+                                      stats.damage_dealt);
+                menu.label(Some(column), &summary, 18, Color::WHITE);
+            }
+            let unlocked = self.unlocked_card.as_ref().and_then(|name| self.cards.get(name));
+            if let (Scene::Victory, Some(card)) = (scene, unlocked) {
+                menu.label(Some(column), &format!("New card unlocked: {}", card.name), 18, Color::YELLOW);
+            }
+            for (label, action) in scene.menu() {
+                buttons.push(menu.button(Some(column), label, Vector2f::new(260.0, 44.0), *action));
+            }
         }
 
-        self.win.display();
+        self.menu = menu;
+        self.menu_scene = Some(scene);
+        self.menu_buttons = buttons;
     }
 
     pub fn run(&mut self) {
         while self.win.is_open() {
             self.events();
-            let dt = self.clock.restart().as_seconds();
-            if !self.is_paused && !self.is_game_over {
-                self.update_and_draw(dt);
-            }
-            if self.is_game_over {
-                self.show_game_over_screen();
+            let frame_time = self.clock.restart().as_seconds().min(self.settings.max_frame_time);
+            if self.watcher.poll(frame_time) {
+                self.reload();
             }
+            self.update(frame_time);
+            self.draw();
         }
     }
+}
 
-    fn game_over_update(&mut self) {
-        if self.buildings[0].get_hp() <= 0.0 {
-            self.is_game_over = true;
-        }
-    }
 
-    fn show_game_over_screen(&mut self) {
-        let mut font = graphics::Font::from_file("src/res/fonts/SourceCodePro.ttf")
-            .expect("Error loading fonts");
-        let mut text = graphics::Text::new("GAME OVER!", &font, 20);
-        text.set_fill_color(Color::WHITE);
-        text.set_origin(Vector2f::new(text.global_bounds().width / 2.0, text.global_bounds().height / 2.0));
-        text.set_position(Vector2f::new(WINDOW_WIDTH as f32 / 2.0, WINDOW_HEIGHT as f32 / 2.0));
-        self.win.draw(&text);
-    }
+fn load_data(settings: &Settings, assets: &AssetLocator) -> Result<(UnitRegistry, WavePlan, CardLibrary), DataError> {
+    let units = UnitRegistry::load(assets, &settings.units_data_dir)?;
+    let waves = WavePlan::load(assets, &settings.waves_file, &units)?;
+    let cards = CardLibrary::load(assets, &settings.cards_file, &units)?;
+    Ok((units, waves, cards))
 }
 
+// Only loose files can change; data read from an archive is not watched.
+fn watch_data_files(settings: &Settings, cli: &CommandLine, assets: &AssetLocator) -> FileWatcher {
+    let config = cli.config.clone().unwrap_or_else(|| PathBuf::from(CONFIG_PATH));
+    let mut files = vec![config];
+    files.extend(assets.path(&settings.waves_file));
+    files.extend(assets.path(&settings.cards_file));
+    FileWatcher::new(files, assets.path(&settings.units_data_dir).into_iter().collect())
+}
+
+// Runs one battle as fast as possible without opening a window and prints how it went.
+pub fn run_headless(settings: &Settings, assets: &AssetLocator) -> Result<(), DataError> {
+    let stats = Rc::new(RefCell::new(BattleStats::default()));
+    let (units, waves, cards) = load_data(settings, assets)?;
+    let textures = TextureManager::new();
+    // The default deck rather than the profile's, so headless runs stay reproducible.
+    let deck = cards.deck.clone();
+    let mut world = World::new(settings, units, waves, cards, &deck, &textures)?;
+    let recorder = Rc::clone(&stats);
+    world.subscribe(move |event| recorder.borrow_mut().record(event));
+
+    let mut elapsed = 0.0;
+    while !world.is_game_over() && !world.is_victory() && elapsed < settings.headless_time_limit {
+        world.step(settings.fixed_timestep);
+        elapsed += settings.fixed_timestep;
+    }
 
-struct AttackInfo {
-    attacked_id: u32,
-    dmg_taken: f32,
+    let outcome = if world.is_victory() {
+        "victory"
+    } else if world.is_game_over() {
+        "defeat"
+    } else {
+        "time limit reached"
+    };
+    let stats = stats.borrow();
+    println!("{} after {:.1}s: enemies defeated {}, units lost {}, leaked {}, damage dealt {:.0}",
+             outcome, elapsed, stats.enemies_defeated, stats.units_lost, stats.enemies_leaked, stats.damage_dealt);
+    Ok(())
 }
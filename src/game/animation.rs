@@ -0,0 +1,154 @@
+use super::serde::Deserialize;
+use super::graphics::IntRect;
+use std::collections::BTreeMap;
+
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlayMode {
+    #[default]
+    Loop,
+    Once,
+}
+
+
+// One row of a sprite sheet, played left to right.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Clip {
+    pub row: u32,
+    pub frames: u32,
+    pub frame_time: f32,
+    #[serde(default)]
+    pub mode: PlayMode,
+    // The frame on which an attack connects.
+    #[serde(default)]
+    pub hit_frame: Option<u32>,
+}
+
+// Clips are keyed by the state they belong to, e.g. "idle" or "attack_waiting".
+#[derive(Clone, Debug, Deserialize)]
+pub struct AnimationSet {
+    pub frame_size: [u32; 2],
+    #[serde(default)]
+    pub clips: BTreeMap<String, Clip>,
+}
+
+
+pub trait AnimationState: Copy {
+    fn clip(self) -> &'static str;
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AnimationEvent {
+    Hit,
+    Finished,
+}
+
+
+// Plays the clip of whatever state its owner is in. Units without animation
+// data still get an animator; it simply never has a frame to show.
+#[derive(Clone, Debug)]
+pub struct Animator {
+    set: Option<AnimationSet>,
+    clip: &'static str,
+    frame: u32,
+    elapsed: f32,
+    hit_fired: bool,
+    finished: bool,
+    facing_left: bool,
+}
+
+impl Animator {
+
+    pub fn new(set: Option<AnimationSet>) -> Animator {
+        Animator { set, clip: "", frame: 0, elapsed: 0.0, hit_fired: false, finished: false, facing_left: false }
+    }
+
+    fn clip_data(&self, clip: &str) -> Option<&Clip> {
+        self.set.as_ref().and_then(|set| set.clips.get(clip))
+    }
+
+    // Switching to another clip starts it from the first frame; asking for the
+    // one already playing changes nothing.
+    pub fn play(&mut self, clip: &'static str) {
+        if clip != self.clip {
+            self.clip = clip;
+            self.restart();
+        }
+    }
+
+    // Swaps in edited clips without replaying a hit the current clip already landed.
+    pub fn set_animation(&mut self, set: Option<AnimationSet>) {
+        self.set = set;
+        self.frame = 0;
+        self.elapsed = 0.0;
+    }
+
+    fn restart(&mut self) {
+        self.frame = 0;
+        self.elapsed = 0.0;
+        self.hit_fired = false;
+        self.finished = false;
+    }
+
+    // Only one-shot clips can time a hit; a looping one never lets go of its owner.
+    pub fn has_hit_frame(&self, clip: &str) -> bool {
+        self.clip_data(clip).is_some_and(|c| c.mode == PlayMode::Once && c.hit_frame.is_some())
+    }
+
+    // A one-shot clip holds its owner until the last frame has been shown.
+    pub fn is_busy(&self) -> bool {
+        !self.finished && self.clip_data(self.clip).is_some_and(|c| c.mode == PlayMode::Once)
+    }
+
+    // Keeps the last facing while standing still.
+    pub fn face(&mut self, velocity: f32) {
+        if velocity < -0.01 {
+            self.facing_left = true;
+        } else if velocity > 0.01 {
+            self.facing_left = false;
+        }
+    }
+
+    pub fn is_facing_left(&self) -> bool { self.facing_left }
+
+    pub fn update(&mut self, dt: f32) -> Option<AnimationEvent> {
+        let (frames, frame_time, mode, hit_frame) = match self.clip_data(self.clip) {
+            Some(clip) => (clip.frames.max(1), clip.frame_time, clip.mode, clip.hit_frame),
+            None => return None,
+        };
+        if self.finished {
+            return None;
+        }
+
+        let mut event = None;
+        self.elapsed += dt;
+        while frame_time > 0.0 && self.elapsed >= frame_time {
+            self.elapsed -= frame_time;
+            if self.frame + 1 < frames {
+                self.frame += 1;
+            } else if mode == PlayMode::Loop {
+                self.frame = 0;
+                self.hit_fired = false;
+            } else {
+                self.finished = true;
+                event = Some(AnimationEvent::Finished);
+                break;
+            }
+        }
+        if let Some(hit_frame) = hit_frame {
+            if !self.hit_fired && self.frame >= hit_frame.min(frames - 1) {
+                self.hit_fired = true;
+                event = Some(AnimationEvent::Hit);
+            }
+        }
+        event
+    }
+
+    pub fn texture_rect(&self) -> Option<IntRect> {
+        let set = self.set.as_ref()?;
+        let clip = set.clips.get(self.clip)?;
+        let [width, height] = set.frame_size;
+        Some(IntRect::new((self.frame * width) as i32, (clip.row * height) as i32, width as i32, height as i32))
+    }
+}
@@ -0,0 +1,116 @@
+use super::serde::Deserialize;
+use super::animation::AnimationSet;
+use super::entity::Stats;
+use super::handle::Team;
+use super::assets::{AssetLocator, AssetError};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+
+// One kind of unit as a designer describes it. Every file in the units directory
+// is a TOML table of archetypes keyed by name, e.g. `[militia]` plus `[militia.stats]`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct UnitArchetype {
+    pub team: Team,
+    pub size: [f32; 2],
+    // Drawn in place of `color` once the texture is available.
+    #[serde(default)]
+    pub sprite: Option<String>,
+    // Treats `sprite` as a sheet of frames, one clip per state.
+    #[serde(default)]
+    pub animation: Option<AnimationSet>,
+    pub color: [u8; 3],
+    pub walk_speed: f32,
+    #[serde(default = "default_friction")]
+    pub friction: f32,
+    // Gold paid out when an enemy of this kind is killed.
+    #[serde(default)]
+    pub bounty: f32,
+    pub stats: Stats,
+}
+
+fn default_friction() -> f32 { 0.2 }
+
+
+#[derive(Debug)]
+pub enum DataError {
+    Asset(AssetError),
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, super::toml::de::Error),
+    Duplicate(PathBuf, String),
+    UnknownArchetype(String),
+    NotAnEnemy(PathBuf, String),
+    NotAPlayerUnit(PathBuf, String),
+    UnknownCard(PathBuf, String),
+    InvalidCard(PathBuf, String, String),
+    InvalidDeck(PathBuf, String),
+}
+
+// Data files are assets too, so a binary started outside the repository finds
+// the ones shipped next to it or packed into the archive.
+pub fn read_text(assets: &AssetLocator, name: &str) -> Result<String, DataError> {
+    let data = assets.read(name).map_err(DataError::Asset)?;
+    String::from_utf8(data.into_owned())
+        .map_err(|e| DataError::Io(PathBuf::from(name), io::Error::new(io::ErrorKind::InvalidData, e)))
+}
+
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataError::Asset(err) => write!(f, "{}", err),
+            DataError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            DataError::Parse(path, err) => write!(f, "{}: {}", path.display(), err),
+            DataError::Duplicate(path, name) => write!(f, "{}: archetype `{}` is defined twice", path.display(), name),
+            DataError::UnknownArchetype(name) => write!(f, "unknown unit archetype `{}`", name),
+            DataError::NotAnEnemy(path, name) => write!(f, "{}: archetype `{}` is not on the enemy team", path.display(), name),
+            DataError::NotAPlayerUnit(path, name) => {
+                write!(f, "{}: archetype `{}` is not on the player team", path.display(), name)
+            },
+            DataError::UnknownCard(path, name) => write!(f, "{}: deck names unknown card `{}`", path.display(), name),
+            DataError::InvalidCard(path, name, msg) => write!(f, "{}: card `{}` {}", path.display(), name, msg),
+            DataError::InvalidDeck(path, msg) => write!(f, "{}: deck {}", path.display(), msg),
+        }
+    }
+}
+
+
+#[derive(Clone, Debug, Default)]
+pub struct UnitRegistry {
+    archetypes: BTreeMap<String, UnitArchetype>,
+}
+
+impl UnitRegistry {
+
+    // Loads every `*.toml` file directly in `dir`, in file name order so errors are reproducible.
+    pub fn load(assets: &AssetLocator, dir: &str) -> Result<UnitRegistry, DataError> {
+        let prefix = format!("{}/", dir.trim_end_matches('/'));
+        let names: Vec<String> = assets.list(dir).into_iter()
+            .filter(|name| name.ends_with(".toml") && !name[prefix.len()..].contains('/'))
+            .collect();
+        if names.is_empty() {
+            return Err(DataError::Asset(AssetError::Missing(PathBuf::from(dir))));
+        }
+
+        let mut registry = UnitRegistry::default();
+        for name in names {
+            let path = PathBuf::from(&name);
+            let text = read_text(assets, &name)?;
+            let archetypes: BTreeMap<String, UnitArchetype> = super::toml::from_str(&text)
+                .map_err(|e| DataError::Parse(path.clone(), e))?;
+            for (name, archetype) in archetypes {
+                if registry.archetypes.contains_key(&name) {
+                    return Err(DataError::Duplicate(path, name));
+                }
+                registry.archetypes.insert(name, archetype);
+            }
+        }
+        Ok(registry)
+    }
+
+    pub fn get(&self, name: &str) -> Result<&UnitArchetype, DataError> {
+        self.archetypes.get(name).ok_or_else(|| DataError::UnknownArchetype(name.to_string()))
+    }
+}
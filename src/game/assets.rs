@@ -0,0 +1,157 @@
+use super::pack::Archive;
+use super::super::settings::Settings;
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+
+// Built by `cargo run --bin pack_assets` before compiling with `--features embed-assets`.
+#[cfg(feature = "embed-assets")]
+static EMBEDDED: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/assets.pak"));
+
+
+#[derive(Debug)]
+pub enum AssetError {
+    NoSources(Vec<String>),
+    Archive(PathBuf, io::Error),
+    Missing(PathBuf),
+    Invalid(PathBuf),
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetError::NoSources(roots) => write!(f, "no assets found: none of [{}] exists in the working directory \
+                                                      or next to the executable, and there is no asset archive",
+                                                   roots.join(", ")),
+            AssetError::Archive(path, err) => write!(f, "{}: {}", path.display(), err),
+            AssetError::Missing(path) => write!(f, "{}: asset is missing", path.display()),
+            AssetError::Invalid(path) => write!(f, "{}: asset could not be loaded", path.display()),
+        }
+    }
+}
+
+
+enum Source {
+    Dir(PathBuf),
+    Archive(Archive),
+}
+
+
+// Finds assets by name, e.g. "textures/units/militia.png" or "data/waves.toml", in
+// the configured root directories first, then the packed archive, then whatever was
+// embedded at build time. A file in an earlier source hides the same name in later ones, so loose
+// files can override a shipped archive.
+pub struct AssetLocator {
+    sources: Vec<Source>,
+}
+
+impl AssetLocator {
+
+    pub fn new(settings: &Settings) -> Result<AssetLocator, AssetError> {
+        // Both copies of a root count, so files missing from the working directory
+        // are still found next to the executable.
+        let mut roots: Vec<PathBuf> = Vec::new();
+        for dir in settings.asset_roots.iter().flat_map(|root| candidates(root)).filter(|dir| dir.is_dir()) {
+            let canonical = fs::canonicalize(&dir).unwrap_or_else(|_| dir.clone());
+            if !roots.contains(&canonical) {
+                roots.push(canonical);
+            }
+        }
+        let mut sources: Vec<Source> = roots.into_iter().map(Source::Dir).collect();
+        if let Some(path) = locate(&settings.asset_archive, Path::is_file) {
+            let data = fs::read(&path).map_err(|e| AssetError::Archive(path.clone(), e))?;
+            let archive = Archive::parse(Cow::Owned(data)).map_err(|e| AssetError::Archive(path, e))?;
+            sources.push(Source::Archive(archive));
+        }
+        #[cfg(feature = "embed-assets")]
+        sources.push(Source::Archive(Archive::parse(Cow::Borrowed(EMBEDDED))
+            .expect("Error loading embedded assets")));
+
+        if sources.is_empty() {
+            return Err(AssetError::NoSources(settings.asset_roots.clone()));
+        }
+        Ok(AssetLocator { sources })
+    }
+
+    pub fn read(&self, name: &str) -> Result<Cow<'_, [u8]>, AssetError> {
+        for source in &self.sources {
+            match source {
+                Source::Dir(root) => {
+                    let path = root.join(name);
+                    if path.is_file() {
+                        return fs::read(&path).map(Cow::Owned).map_err(|_| AssetError::Invalid(path));
+                    }
+                },
+                Source::Archive(archive) => {
+                    if let Some(data) = archive.get(name) {
+                        return Ok(Cow::Borrowed(data));
+                    }
+                },
+            }
+        }
+        Err(AssetError::Missing(PathBuf::from(name)))
+    }
+
+    // Where `name` is on disk, if a root directory has it; archives have no paths.
+    pub fn path(&self, name: &str) -> Option<PathBuf> {
+        self.sources.iter().find_map(|source| match source {
+            Source::Dir(root) => Some(root.join(name)).filter(|path| path.exists()),
+            Source::Archive(_) => None,
+        })
+    }
+
+    // Every asset below `dir` in any source, by full name.
+    pub fn list(&self, dir: &str) -> BTreeSet<String> {
+        let prefix = format!("{}/", dir.trim_end_matches('/'));
+        let mut names = BTreeSet::new();
+        for source in &self.sources {
+            match source {
+                Source::Dir(root) => list_dir(&root.join(dir), &prefix, &mut names),
+                Source::Archive(archive) => {
+                    names.extend(archive.names().filter(|name| name.starts_with(&prefix)).map(str::to_string));
+                },
+            }
+        }
+        names
+    }
+}
+
+// A relative path is looked up from the working directory first and then next to
+// the executable, so a binary launched from anywhere finds what ships with it.
+fn candidates(path: &str) -> Vec<PathBuf> {
+    let path = Path::new(path);
+    let mut candidates = vec![path.to_path_buf()];
+    if path.is_relative() {
+        if let Some(exe_dir) = env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf)) {
+            candidates.push(exe_dir.join(path));
+        }
+    }
+    candidates
+}
+
+fn locate(path: &str, exists: fn(&Path) -> bool) -> Option<PathBuf> {
+    candidates(path).into_iter().find(|candidate| exists(candidate))
+}
+
+fn list_dir(dir: &Path, prefix: &str, names: &mut BTreeSet<String>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        let name = match path.file_name().and_then(|n| n.to_str()) {
+            Some(file_name) => format!("{}{}", prefix, file_name),
+            None => continue,
+        };
+        if path.is_dir() {
+            list_dir(&path, &format!("{}/", name), names);
+        } else {
+            names.insert(name);
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use super::graphics::View;
+use super::sfml::system::{SfBox, Vector2f};
+
+
+// Scrolls horizontally over a world that may be wider than the window. The
+// view always stays inside the world; a world narrower than the window is centred.
+pub struct Camera {
+    center_x: f32,
+    size: Vector2f,
+    world_width: f32,
+    drag: Option<(i32, f32)>,
+    mouse_x: Option<i32>,
+}
+
+impl Camera {
+
+    pub fn new(size: Vector2f, world_width: f32) -> Camera {
+        Camera { center_x: world_width / 2.0, size, world_width, drag: None, mouse_x: None }
+    }
+
+    pub fn view(&self) -> SfBox<View> {
+        View::new(Vector2f::new(self.center_x, self.size.y / 2.0), self.size)
+    }
+
+    pub fn center_x(&self) -> f32 { self.center_x }
+
+    pub fn set_world_width(&mut self, world_width: f32) {
+        self.world_width = world_width;
+        self.pan(0.0);
+    }
+
+    pub fn pan(&mut self, dx: f32) {
+        let half = self.size.x / 2.0;
+        self.center_x = if self.world_width <= self.size.x {
+            self.world_width / 2.0
+        } else {
+            (self.center_x + dx).clamp(half, self.world_width - half)
+        };
+    }
+
+    pub fn start_drag(&mut self, x: i32) {
+        self.drag = Some((x, self.center_x));
+    }
+
+    pub fn end_drag(&mut self) {
+        self.drag = None;
+    }
+
+    // Mouse x inside the window, or `None` once it leaves.
+    pub fn mouse_moved(&mut self, x: Option<i32>) {
+        self.mouse_x = x;
+        if let (Some((from, center_x)), Some(x)) = (self.drag, x) {
+            self.center_x = center_x;
+            self.pan((from - x) as f32);
+        }
+    }
+
+    // `direction` is -1, 0 or 1 from the keyboard; the mouse near either window
+    // edge scrolls too unless a drag is in progress.
+    pub fn update(&mut self, frame_time: f32, direction: f32, speed: f32, edge_margin: f32) {
+        let mut direction = direction;
+        if let (None, Some(x)) = (self.drag, self.mouse_x) {
+            if (x as f32) < edge_margin {
+                direction -= 1.0;
+            } else if x as f32 > self.size.x - edge_margin {
+                direction += 1.0;
+            }
+        }
+        self.pan(direction.clamp(-1.0, 1.0) * speed * frame_time);
+    }
+}
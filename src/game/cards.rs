@@ -0,0 +1,465 @@
+use super::serde::Deserialize;
+use super::archetype::{UnitRegistry, DataError};
+use super::entity::BuildingType;
+use super::world::PlacementError;
+use super::handle::Team;
+use super::timer::Cooldown;
+use super::rand::SeedableRng;
+use super::rand::rngs::StdRng;
+use super::rand::seq::SliceRandom;
+use crate::settings::Settings;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use super::assets::AssetLocator;
+use std::path::Path;
+
+
+// What playing a card does at the ground position it was dropped on.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum CardEffect {
+    Spawn { archetype: String },
+    Build { building: BuildingType },
+    // Spells hit everything of the right team within `radius` of the target.
+    Fireball { damage: f32, radius: f32 },
+    Heal { amount: f32, radius: f32 },
+}
+
+impl CardEffect {
+
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            CardEffect::Spawn { .. } => "Unit",
+            CardEffect::Build { .. } => "Building",
+            CardEffect::Fireball { .. } | CardEffect::Heal { .. } => "Spell",
+        }
+    }
+
+    // Width and height of what the card puts on the ground; a spell covers its
+    // whole radius.
+    pub fn footprint(&self, units: &UnitRegistry, settings: &Settings) -> [f32; 2] {
+        match self {
+            CardEffect::Spawn { archetype } => units.get(archetype).map_or([0.0, 0.0], |a| a.size),
+            CardEffect::Build { building } => {
+                let size = building.size(settings);
+                [size.x, size.y]
+            },
+            CardEffect::Fireball { radius, .. } | CardEffect::Heal { radius, .. } => [radius * 2.0, 12.0],
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CardDefinition {
+    pub name: String,
+    // Gold paid when the card is played.
+    pub cost: f32,
+    pub effect: CardEffect,
+}
+
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct DrawRules {
+    pub hand_size: usize,
+    pub starting_hand: usize,
+    // Cards drawn whenever a wave is cleared.
+    pub per_wave: usize,
+    // Seconds between two timed draws, 0 turns them off.
+    pub interval: f32,
+}
+
+impl Default for DrawRules {
+    fn default() -> DrawRules {
+        DrawRules { hand_size: 5, starting_hand: 3, per_wave: 2, interval: 20.0 }
+    }
+}
+
+
+// What the deck builder lets a player put into a deck.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct DeckRules {
+    pub min_size: usize,
+    pub max_size: usize,
+    // Copies of any one card a deck may hold.
+    pub max_copies: usize,
+}
+
+impl Default for DeckRules {
+    fn default() -> DeckRules {
+        DeckRules { min_size: 6, max_size: 15, max_copies: 3 }
+    }
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeckError {
+    TooSmall { size: usize, min: usize },
+    TooLarge { max: usize },
+    TooManyCopies { card: String, max: usize },
+    Unknown(String),
+    Locked(String),
+}
+
+impl fmt::Display for DeckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeckError::TooSmall { size, min } => write!(f, "needs at least {} cards, has {}", min, size),
+            DeckError::TooLarge { max } => write!(f, "can hold at most {} cards", max),
+            DeckError::TooManyCopies { card, max } => write!(f, "can hold at most {} copies of `{}`", max, card),
+            DeckError::Unknown(card) => write!(f, "holds `{}`, which does not exist", card),
+            DeckError::Locked(card) => write!(f, "holds `{}`, which is not unlocked yet", card),
+        }
+    }
+}
+
+
+// Everything the cards file defines: the cards themselves, the default deck, what
+// a deck may hold and how cards are drawn.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct CardLibrary {
+    // Also the cards a new profile starts with unlocked.
+    #[serde(default)]
+    pub deck: Vec<String>,
+    #[serde(default)]
+    pub rules: DeckRules,
+    #[serde(default)]
+    pub draw: DrawRules,
+    #[serde(default, rename = "card")]
+    cards: BTreeMap<String, CardDefinition>,
+}
+
+impl CardLibrary {
+
+    // Spawn cards must name player archetypes, the base cannot be built twice and
+    // the deck may only hold cards that exist.
+    pub fn load(assets: &AssetLocator, name: &str, units: &UnitRegistry) -> Result<CardLibrary, DataError> {
+        let path = Path::new(name);
+        let text = super::archetype::read_text(assets, name)?;
+        let library: CardLibrary = super::toml::from_str(&text).map_err(|e| DataError::Parse(path.to_path_buf(), e))?;
+        for (name, card) in &library.cards {
+            let invalid = |msg: &str| DataError::InvalidCard(path.to_path_buf(), name.clone(), msg.to_string());
+            match &card.effect {
+                CardEffect::Spawn { archetype } if units.get(archetype)?.team != Team::Player => {
+                    return Err(invalid("can only spawn player units"));
+                },
+                CardEffect::Build { building: BuildingType::Base } => {
+                    return Err(invalid("cannot build a second base"));
+                },
+                _ => {},
+            }
+        }
+        for name in &library.deck {
+            if library.get(name).is_none() {
+                return Err(DataError::UnknownCard(path.to_path_buf(), name.clone()));
+            }
+        }
+        library.check_deck(&library.deck).map_err(|e| DataError::InvalidDeck(path.to_path_buf(), e.to_string()))?;
+        Ok(library)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CardDefinition> {
+        self.cards.get(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.cards.keys().map(String::as_str)
+    }
+
+    // Size and copy limits; which cards are unlocked is up to the profile.
+    pub fn check_deck(&self, deck: &[String]) -> Result<(), DeckError> {
+        let rules = self.rules;
+        if deck.len() > rules.max_size {
+            return Err(DeckError::TooLarge { max: rules.max_size });
+        }
+        let mut copies = BTreeMap::new();
+        for name in deck {
+            if self.get(name).is_none() {
+                return Err(DeckError::Unknown(name.clone()));
+            }
+            *copies.entry(name).or_insert(0) += 1;
+        }
+        if let Some((card, _)) = copies.into_iter().find(|(_, count)| *count > rules.max_copies) {
+            return Err(DeckError::TooManyCopies { card: card.clone(), max: rules.max_copies });
+        }
+        if deck.len() < rules.min_size {
+            return Err(DeckError::TooSmall { size: deck.len(), min: rules.min_size });
+        }
+        Ok(())
+    }
+
+    // The cards a new profile starts with.
+    pub fn starter_cards(&self) -> BTreeSet<String> {
+        self.deck.iter().cloned().collect()
+    }
+}
+
+
+#[derive(Debug)]
+pub enum PlayError {
+    NoCard(usize),
+    NotEnoughGold { cost: f32, gold: f32 },
+    Placement(PlacementError),
+    Data(DataError),
+}
+
+impl fmt::Display for PlayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlayError::NoCard(index) => write!(f, "there is no card {} in the hand", index + 1),
+            PlayError::NotEnoughGold { cost, gold } => write!(f, "needs {:.0} gold, have {:.0}", cost, gold.floor()),
+            PlayError::Placement(err) => write!(f, "{}", err),
+            PlayError::Data(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+
+// The cards of one battle. Cards are named by their key in the library and move
+// from the draw pile to the hand to the discard pile; an empty draw pile is
+// refilled by shuffling the discards back in. Nothing here needs a window, so
+// headless battles play cards the same way.
+pub struct Cards {
+    library: CardLibrary,
+    draw_pile: Vec<String>,
+    discard_pile: Vec<String>,
+    hand: Vec<String>,
+    draw_timer: Cooldown,
+    rng: StdRng,
+}
+
+impl Cards {
+
+    pub fn new(library: CardLibrary, deck: &[String], seed: u64) -> Cards {
+        let rules = library.draw;
+        let mut cards = Cards {
+            draw_pile: deck.to_vec(),
+            discard_pile: Vec::new(),
+            hand: Vec::new(),
+            draw_timer: Cooldown::new(rules.interval),
+            rng: StdRng::seed_from_u64(seed),
+            library,
+        };
+        cards.draw_pile.shuffle(&mut cards.rng);
+        cards.draw(rules.starting_hand);
+        cards
+    }
+
+    // Reloaded cards take effect straight away; copies of cards that no longer
+    // exist are dropped from every pile.
+    pub fn set_library(&mut self, library: CardLibrary) {
+        self.draw_timer.set_duration(library.draw.interval);
+        self.library = library;
+        let library = &self.library;
+        for pile in [&mut self.draw_pile, &mut self.discard_pile, &mut self.hand] {
+            pile.retain(|name| library.get(name).is_some());
+        }
+    }
+
+    pub fn hand(&self) -> &[String] { &self.hand }
+    pub fn draw_pile_len(&self) -> usize { self.draw_pile.len() }
+    pub fn discard_pile_len(&self) -> usize { self.discard_pile.len() }
+
+    pub fn hand_card(&self, index: usize) -> Option<&CardDefinition> {
+        self.hand.get(index).and_then(|name| self.library.get(name))
+    }
+
+    // Draws until `count` cards were drawn, the hand is full or no cards are left.
+    // Returns how many were drawn.
+    pub fn draw(&mut self, count: usize) -> usize {
+        let mut drawn = 0;
+        while drawn < count && self.hand.len() < self.library.draw.hand_size {
+            if self.draw_pile.is_empty() {
+                if self.discard_pile.is_empty() {
+                    break;
+                }
+                self.draw_pile.append(&mut self.discard_pile);
+                self.draw_pile.shuffle(&mut self.rng);
+            }
+            if let Some(card) = self.draw_pile.pop() {
+                self.hand.push(card);
+                drawn += 1;
+            }
+        }
+        drawn
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        if self.library.draw.interval <= 0.0 {
+            return;
+        }
+        self.draw_timer.tick(dt);
+        if self.draw_timer.is_ready() {
+            self.draw(1);
+            self.draw_timer.trigger();
+        }
+    }
+
+    pub fn wave_cleared(&mut self) {
+        self.draw(self.library.draw.per_wave);
+    }
+
+    // Moves a played card from the hand to the discard pile.
+    pub fn discard(&mut self, index: usize) -> Option<String> {
+        if index >= self.hand.len() {
+            return None;
+        }
+        let card = self.hand.remove(index);
+        self.discard_pile.push(card.clone());
+        Some(card)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIBRARY: &str = r#"
+        deck = ["Bolt", "Bolt", "Bolt", "Wall", "Wall", "Wall"]
+
+        [draw]
+        hand_size = 4
+        starting_hand = 2
+        per_wave = 2
+        interval = 10.0
+
+        [card.Bolt]
+        name = "Bolt"
+        cost = 30.0
+        effect = { kind = "fireball", damage = 20.0, radius = 50.0 }
+
+        [card.Wall]
+        name = "Wall"
+        cost = 50.0
+        effect = { kind = "build", building = "others" }
+    "#;
+
+    fn library() -> CardLibrary {
+        super::super::toml::from_str(LIBRARY).unwrap()
+    }
+
+    fn cards() -> Cards {
+        let library = library();
+        let deck = library.deck.clone();
+        Cards::new(library, &deck, 7)
+    }
+
+    fn total(cards: &Cards) -> usize {
+        cards.hand().len() + cards.draw_pile_len() + cards.discard_pile_len()
+    }
+
+    #[test]
+    fn starts_with_the_starting_hand() {
+        let cards = cards();
+        assert_eq!(cards.hand().len(), 2);
+        assert_eq!(cards.draw_pile_len(), 4);
+        assert_eq!(cards.discard_pile_len(), 0);
+    }
+
+    #[test]
+    fn same_seed_same_order() {
+        let (mut a, mut b) = (cards(), cards());
+        a.draw(2);
+        b.draw(2);
+        assert_eq!(a.hand(), b.hand());
+    }
+
+    #[test]
+    fn draw_stops_at_the_hand_limit() {
+        let mut cards = cards();
+        assert_eq!(cards.draw(10), 2);
+        assert_eq!(cards.hand().len(), 4);
+        assert_eq!(cards.draw(1), 0);
+        assert_eq!(total(&cards), 6);
+    }
+
+    #[test]
+    fn discard_moves_the_card_to_the_discard_pile() {
+        let mut cards = cards();
+        let first = cards.hand()[0].clone();
+        assert_eq!(cards.discard(0), Some(first));
+        assert_eq!(cards.hand().len(), 1);
+        assert_eq!(cards.discard_pile_len(), 1);
+        assert_eq!(cards.discard(5), None);
+        assert_eq!(cards.discard_pile_len(), 1);
+    }
+
+    #[test]
+    fn empty_draw_pile_reshuffles_the_discards() {
+        let mut cards = cards();
+        cards.draw(2);
+        while cards.draw_pile_len() > 0 {
+            cards.discard(0);
+            cards.draw(1);
+        }
+        let discarded = cards.discard_pile_len();
+        assert!(discarded > 0);
+        cards.discard(0);
+        assert_eq!(cards.draw(1), 1);
+        assert_eq!(cards.draw_pile_len(), discarded);
+        assert_eq!(cards.discard_pile_len(), 0);
+        assert_eq!(total(&cards), 6);
+    }
+
+    #[test]
+    fn nothing_left_to_draw() {
+        let library = library();
+        let mut cards = Cards::new(library, &["Bolt".to_string()], 7);
+        assert_eq!(cards.hand().len(), 1);
+        assert_eq!(cards.draw(1), 0);
+    }
+
+    #[test]
+    fn timed_and_wave_draws() {
+        let mut cards = cards();
+        cards.update(9.0);
+        assert_eq!(cards.hand().len(), 2);
+        cards.update(1.0);
+        assert_eq!(cards.hand().len(), 3);
+        cards.wave_cleared();
+        assert_eq!(cards.hand().len(), 4);
+    }
+
+    #[test]
+    fn zero_interval_turns_timed_draws_off() {
+        let mut library = library();
+        library.draw.interval = 0.0;
+        let deck = library.deck.clone();
+        let mut cards = Cards::new(library, &deck, 7);
+        cards.update(100.0);
+        assert_eq!(cards.hand().len(), 2);
+    }
+
+    #[test]
+    fn reload_drops_removed_cards() {
+        let mut cards = cards();
+        let mut library: CardLibrary = super::super::toml::from_str(LIBRARY).unwrap();
+        library.cards.remove("Wall");
+        cards.set_library(library);
+        assert_eq!(total(&cards), 3);
+        assert!(cards.hand().iter().all(|name| name == "Bolt"));
+    }
+
+    #[test]
+    fn deck_rules() {
+        let library = library();
+        let deck = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(library.check_deck(&library.deck), Ok(()));
+        assert_eq!(library.check_deck(&deck(&["Bolt"])), Err(DeckError::TooSmall { size: 1, min: 6 }));
+        assert_eq!(library.check_deck(&deck(&["Bolt"; 16])), Err(DeckError::TooLarge { max: 15 }));
+        assert_eq!(
+            library.check_deck(&deck(&["Bolt", "Bolt", "Bolt", "Bolt", "Wall", "Wall"])),
+            Err(DeckError::TooManyCopies { card: "Bolt".to_string(), max: 3 }),
+        );
+        assert_eq!(library.check_deck(&deck(&["Nope"])), Err(DeckError::Unknown("Nope".to_string())));
+    }
+
+    #[test]
+    fn play_errors() {
+        assert_eq!(PlayError::NoCard(2).to_string(), "there is no card 3 in the hand");
+        let err = PlayError::NotEnoughGold { cost: 50.0, gold: 49.9 };
+        assert_eq!(err.to_string(), "needs 50 gold, have 49");
+    }
+}
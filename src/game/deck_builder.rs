@@ -0,0 +1,209 @@
+use super::graphics::{Color, Font, RenderWindow};
+use super::sfml::system::Vector2f;
+use super::ui::{Ui, Direction, PanelStyle, Align};
+use super::cards::CardLibrary;
+use super::profile::Profile;
+use super::scene::Scene;
+
+
+const MAX_NAME_LENGTH: usize = 24;
+
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DeckAction {
+    // Index into the collection, which lists every card of the library.
+    Add(usize),
+    Remove(usize),
+    PrevDeck,
+    NextDeck,
+    NewDeck,
+    // Starts typing a new name for the active preset.
+    Rename,
+    DeleteDeck,
+    Back,
+}
+
+
+// Screen for editing the profile's deck presets: the collection on the left adds
+// a copy to the active deck, the deck on the right takes one out. The widgets are
+// rebuilt after every change, like the menus.
+pub struct DeckBuilder<'a> {
+    font: &'a Font,
+    window_size: Vector2f,
+    ui: Ui<'a, DeckAction>,
+    collection: Vec<String>,
+    // Why the last change was refused, or why the deck cannot be used yet.
+    status: Option<String>,
+    // The name typed so far while renaming the active preset.
+    new_name: Option<String>,
+    pointer: Vector2f,
+}
+
+impl<'a> DeckBuilder<'a> {
+
+    pub fn new(font: &'a Font, window_size: Vector2f) -> DeckBuilder<'a> {
+        DeckBuilder {
+            font,
+            window_size,
+            ui: Ui::new(font),
+            collection: Vec::new(),
+            status: None,
+            new_name: None,
+            pointer: Vector2f::new(0.0, 0.0),
+        }
+    }
+
+    pub fn rebuild(&mut self, profile: &Profile, library: &CardLibrary) {
+        let rules = library.rules;
+        let mut ui = Ui::new(self.font);
+        self.collection = library.names().map(str::to_string).collect();
+
+        let style = PanelStyle { spacing: 16.0, align: Align::Center, ..PanelStyle::default() };
+        let column = ui.panel(None, Direction::Vertical, style);
+        ui.set_anchor(column, self.window_size / 2.0, Vector2f::new(0.5, 0.5));
+        ui.label(Some(column), Scene::DeckBuilder.title(), 40, Color::WHITE);
+
+        let presets_style = PanelStyle { spacing: 12.0, align: Align::Center, ..PanelStyle::default() };
+        let presets = ui.panel(Some(column), Direction::Horizontal, presets_style);
+        ui.button(Some(presets), "<", Vector2f::new(44.0, 36.0), DeckAction::PrevDeck);
+        let title = match &self.new_name {
+            Some(name) => format!("{}_", name),
+            None => format!("{}  {}/{} cards", profile.active_deck, profile.deck().len(), rules.max_size),
+        };
+        ui.label(Some(presets), &title, 22, Color::WHITE);
+        ui.button(Some(presets), ">", Vector2f::new(44.0, 36.0), DeckAction::NextDeck);
+        ui.button(Some(presets), "New", Vector2f::new(100.0, 36.0), DeckAction::NewDeck);
+        ui.button(Some(presets), "Rename", Vector2f::new(100.0, 36.0), DeckAction::Rename);
+        ui.button(Some(presets), "Delete", Vector2f::new(100.0, 36.0), DeckAction::DeleteDeck);
+
+        let lists_style = PanelStyle { spacing: 40.0, ..PanelStyle::default() };
+        let lists = ui.panel(Some(column), Direction::Horizontal, lists_style);
+        let list_style = PanelStyle { padding: 12.0, spacing: 6.0, background: Color::rgba(0, 0, 0, 140), align: Align::Start };
+        let collection = ui.panel(Some(lists), Direction::Vertical, list_style);
+        ui.label(Some(collection), "Collection", 20, Color::WHITE);
+        let deck = ui.panel(Some(lists), Direction::Vertical, list_style);
+        ui.label(Some(deck), "Deck", 20, Color::WHITE);
+
+        for (index, name) in self.collection.iter().enumerate() {
+            let card = match library.get(name) {
+                Some(card) => card,
+                None => continue,
+            };
+            let copies = profile.copies(name);
+            if profile.unlocked.contains(name) {
+                let text = format!("{}  {:.0}g  {}/{}", card.name, card.cost, copies, rules.max_copies);
+                ui.button(Some(collection), &text, Vector2f::new(280.0, 32.0), DeckAction::Add(index));
+            } else {
+                ui.label(Some(collection), &format!("{}  (locked)", card.name), 16, Color::rgb(120, 120, 120));
+            }
+            if copies > 0 {
+                let text = format!("{} x{}", card.name, copies);
+                ui.button(Some(deck), &text, Vector2f::new(220.0, 32.0), DeckAction::Remove(index));
+            }
+        }
+        if profile.deck().is_empty() {
+            ui.label(Some(deck), "Empty", 16, Color::rgb(120, 120, 120));
+        }
+
+        match (&self.status, profile.check_deck(library)) {
+            (Some(status), _) => ui.label(Some(column), status, 18, Color::RED),
+            (None, Err(err)) => ui.label(Some(column), &format!("This deck {}", err), 18, Color::RED),
+            (None, Ok(())) => ui.label(Some(column), "Ready for battle", 18, Color::GREEN),
+        };
+        let hint = match self.new_name {
+            Some(_) => "Type a name, Enter to keep it, Esc to cancel".to_string(),
+            None => format!("{} to {} cards, at most {} copies of each", rules.min_size, rules.max_size, rules.max_copies),
+        };
+        ui.label(Some(column), &hint, 14, Color::rgb(180, 180, 180));
+        ui.button(Some(column), "Back", Vector2f::new(260.0, 44.0), DeckAction::Back);
+
+        ui.mouse_moved(self.pointer);
+        self.ui = ui;
+    }
+
+    // Applies a change to the profile. Returns true once the player leaves with a
+    // deck that can be played; an unplayable one keeps them here.
+    pub fn apply(&mut self, action: DeckAction, profile: &mut Profile, library: &CardLibrary) -> bool {
+        self.status = None;
+        // Anything else the player does drops a half-typed name.
+        self.new_name = None;
+        let card = match action {
+            DeckAction::Add(index) | DeckAction::Remove(index) => self.collection.get(index).cloned().unwrap_or_default(),
+            _ => String::new(),
+        };
+        match action {
+            DeckAction::Add(_) => {
+                if let Err(err) = profile.add_card(&card, library) {
+                    self.status = Some(format!("This deck {}", err));
+                }
+            },
+            DeckAction::Remove(_) => {
+                profile.remove_card(&card);
+            },
+            DeckAction::PrevDeck => profile.cycle_deck(false),
+            DeckAction::NextDeck => profile.cycle_deck(true),
+            DeckAction::NewDeck => profile.new_deck(),
+            DeckAction::Rename => self.new_name = Some(profile.active_deck.clone()),
+            DeckAction::DeleteDeck => {
+                if !profile.delete_deck() {
+                    self.status = Some("The last deck cannot be deleted".to_string());
+                }
+            },
+            DeckAction::Back => {
+                if let Err(err) = profile.check_deck(library) {
+                    self.status = Some(format!("{} {}", profile.active_deck, err));
+                } else {
+                    return true;
+                }
+            },
+        }
+        self.rebuild(profile, library);
+        false
+    }
+
+    pub fn is_renaming(&self) -> bool {
+        self.new_name.is_some()
+    }
+
+    // Typing while renaming: Backspace deletes, Enter renames the preset.
+    pub fn text_entered(&mut self, c: char, profile: &mut Profile, library: &CardLibrary) {
+        let name = match &mut self.new_name {
+            Some(name) => name,
+            None => return,
+        };
+        match c {
+            '\u{8}' => {
+                name.pop();
+            },
+            '\r' | '\n' => {
+                if !profile.rename_deck(name) {
+                    self.status = Some("A deck needs a name no other deck has".to_string());
+                }
+                self.new_name = None;
+            },
+            c if !c.is_control() && name.chars().count() < MAX_NAME_LENGTH => name.push(c),
+            _ => return,
+        }
+        self.rebuild(profile, library);
+    }
+
+    pub fn cancel_rename(&mut self, profile: &Profile, library: &CardLibrary) {
+        if self.new_name.take().is_some() {
+            self.rebuild(profile, library);
+        }
+    }
+
+    pub fn mouse_moved(&mut self, pos: Vector2f) {
+        self.pointer = pos;
+        self.ui.mouse_moved(pos);
+    }
+
+    pub fn click(&mut self, pos: Vector2f) -> Option<DeckAction> {
+        self.pointer = pos;
+        self.ui.click(pos)
+    }
+
+    pub fn draw(&mut self, win: &mut RenderWindow) {
+        self.ui.draw(win);
+    }
+}
@@ -1,18 +1,22 @@
 use super::{graphics, system};
-use super::sfml::graphics::{Shape, Transformable, Color, RenderTarget, RectangleShape};
-use super::rand;
-use super::super::settings::{HUMANS_HEIGHT, HUMANS_POS_Y, GROUND_HEIGHT, GROUND_POS_Y, WINDOW_WIDTH,
-                            HUMANS_IDLE_WALK_SPEED_FACTOR, HUMANS_WALK_SPEED, ENEMY_WALK_SPEED,
-                            HUMANS_MAX_HP, BUILDING_BASE_MAX_HP, BUILDING_OTHERS_MAX_HP};
+use super::sfml::graphics::{Shape, Transformable, RectangleShape};
+use super::super::settings::{Settings, BoundaryPolicy};
+use super::serde::Deserialize;
 use super::rand::Rng;
+use super::state_machine::{self, StateMachine, StateMachineOwner, StateContext};
+use super::timer::Cooldown;
+use super::handle::Handle;
+use super::archetype::UnitArchetype;
+use super::animation::{AnimationEvent, AnimationState, Animator};
+use super::texture_manager::TextureManager;
 use super::ui::DrawHP;
-use super::sfml::system::{Vector2f, Vector2};
+use super::sfml::system::Vector2f;
 use crate::game::ui::GeoInfo;
 use crate::game::EnemyComing;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum HumanState {
     Idle,
     Walking,
@@ -21,71 +25,269 @@ pub enum HumanState {
     AttackWaiting,
 }
 
+impl AnimationState for HumanState {
+    fn clip(self) -> &'static str {
+        match self {
+            HumanState::Idle => "idle",
+            HumanState::Walking => "walking",
+            HumanState::Running => "running",
+            HumanState::Attacking => "attacking",
+            HumanState::AttackWaiting => "attack_waiting",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum EnemyState {
     Running,
     Attacking,
     AttackWaiting,
 }
 
+impl AnimationState for EnemyState {
+    fn clip(self) -> &'static str {
+        match self {
+            EnemyState::Running => "running",
+            EnemyState::Attacking => "attacking",
+            EnemyState::AttackWaiting => "attack_waiting",
+        }
+    }
+}
+
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum BuildingType {
     Base,
     Others,
 }
 
+impl BuildingType {
+
+    pub fn sprite(self) -> &'static str {
+        match self {
+            BuildingType::Base => "buildings/base.png",
+            BuildingType::Others => "buildings/others.png",
+        }
+    }
+
+    pub fn size(self, settings: &Settings) -> Vector2f {
+        let [width, height] = match self {
+            BuildingType::Base => settings.building_base_size,
+            BuildingType::Others => settings.building_others_size,
+        };
+        Vector2f::new(width, height)
+    }
+}
+
 
-struct PhysicalStates {
+pub struct PhysicalStates {
     velocity: f32,
     friction: f32,
+    walk_speed: f32,
+}
+
+impl PhysicalStates {
+
+    fn new(archetype: &UnitArchetype) -> PhysicalStates {
+        PhysicalStates {
+            velocity: 0.0,
+            friction: archetype.friction,
+            walk_speed: archetype.walk_speed,
+        }
+    }
+}
+
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Stats {
+    pub max_hp: f32,
+    #[serde(default)]
+    pub armor: f32,
+    pub attack_damage: f32,
+    pub attack_interval: f32,
+    // Reach beyond touching distance, 0 means melee.
+    #[serde(default)]
+    pub range: f32,
 }
 
+impl Stats {
+
+    pub fn building(building_type: BuildingType, settings: &Settings) -> Stats {
+        let (max_hp, armor) = match building_type {
+            BuildingType::Base => (settings.building_base_max_hp, settings.building_base_armor),
+            BuildingType::Others => (settings.building_others_max_hp, settings.building_others_armor),
+        };
+        Stats {
+            max_hp,
+            armor,
+            attack_damage: 0.0,
+            attack_interval: 0.0,
+            range: 0.0,
+        }
+    }
 
-struct EntityFightStatus {
-    attack_damage: f32,
-    armor: f32,
-    // 是否是近战
-    is_infant: Option<f32>,
+    // Armor never blocks a hit completely: every 100 points halve the damage taken.
+    pub fn mitigate(&self, dmg: f32) -> f32 {
+        dmg * 100.0 / (100.0 + self.armor.max(0.0))
+    }
+}
+
+
+pub struct EntityFightStatus {
+    stats: Stats,
     hp: f32,
 }
 
+impl EntityFightStatus {
+
+    pub fn new(stats: Stats) -> EntityFightStatus {
+        EntityFightStatus { hp: stats.max_hp, stats }
+    }
+
+    // Swaps the stat block but keeps the share of HP left, so a unit at full
+    // health stays at full health when its max HP changes.
+    pub fn set_stats(&mut self, stats: Stats) {
+        let ratio = if self.stats.max_hp > 0.0 { self.hp / self.stats.max_hp } else { 1.0 };
+        self.hp = (stats.max_hp * ratio).clamp(0.0, stats.max_hp);
+        self.stats = stats;
+    }
+}
+
 
 pub trait Damageable {
-    fn get_id(&self) -> u32;
-    fn get_hp(&self) -> f32;
-    fn fight_status(&mut self) -> &mut EntityFightStatus;
+    fn get_handle(&self) -> Handle;
+    fn fight_status(&self) -> &EntityFightStatus;
+    fn fight_status_mut(&mut self) -> &mut EntityFightStatus;
+
+    fn stats(&self) -> &Stats { &self.fight_status().stats }
+    fn get_hp(&self) -> f32 { self.fight_status().hp }
+
     fn set_hp(&mut self, new_hp: f32) {
-        if new_hp < 0.0 { self.fight_status().hp = 0.0; }
-        else if new_hp > HUMANS_MAX_HP { self.fight_status().hp = HUMANS_MAX_HP; }
-        else { self.fight_status().hp = new_hp; }
+        let max_hp = self.stats().max_hp;
+        self.fight_status_mut().hp = new_hp.clamp(0.0, max_hp);
+    }
+
+    // Returns the damage actually taken after armor.
+    fn take_damage(&mut self, dmg: f32) -> f32 {
+        let taken = self.stats().mitigate(dmg);
+        self.set_hp(self.get_hp() - taken);
+        taken
     }
 }
 
 
-pub trait Entity<'this, T> : Damageable + GeoInfo {
-    fn image(&mut self) -> &mut RectangleShape<'this>;
+pub trait Combatant: Damageable {
+    fn attack_cooldown(&self) -> &Cooldown;
+    fn attack_cooldown_mut(&mut self) -> &mut Cooldown;
 
-    fn is_player(&self) -> bool;
+    fn target_in_reach(&self) -> Option<Handle>;
+    fn set_target_in_reach(&mut self, target: Option<Handle>);
 
-    fn current_state(&self) -> &T;
-    fn set_current_state(&mut self, state: T);
+    fn attack_target(&mut self) -> &mut Option<(Handle, f32)>;
+    // The rival a swing that just started is aimed at, until the world reports it.
+    fn swing_target(&mut self) -> &mut Option<Handle>;
 
-    fn physical_states(&mut self) -> &mut PhysicalStates;
+    fn strike(&mut self) {
+        if let Some(target) = self.target_in_reach() {
+            let dmg = self.stats().attack_damage;
+            *self.attack_target() = Some((target, dmg));
+        }
+    }
 
-    fn state_timer(&mut self) -> &mut system::Clock;
-    fn attack_timer(&mut self) -> &mut system::Clock;
+    // Whether the attack animation decides when `strike` lands instead of the
+    // moment the swing starts.
+    fn strike_on_hit_frame(&self) -> bool { false }
 
-    fn attack_target(&mut self) -> &mut Option<(u32, f32)>;
-    fn set_attack_target(&mut self, target: Option<(u32, f32)>);
+    fn swing_finished(&self) -> bool { true }
 
-    fn rival_dir(&self) -> &Option<EnemyComing>;
-    fn set_rival_dir(&mut self, dir: Option<EnemyComing>);
+    fn get_attack_target(&mut self) -> Option<(Handle, f32)> {
+        self.attack_target().take()
+    }
+
+    fn get_swing_target(&mut self) -> Option<Handle> {
+        self.swing_target().take()
+    }
+}
+
+
+// Shared Attacking/AttackWaiting cycle: engage when a rival gets in reach, swing
+// whenever the cooldown allows, and fall back to `disengage_to` once nothing is left.
+// A swing lasts as long as its attack animation and strikes on the hit frame.
+pub fn attack_loop<S, C>(machine: StateMachine<S, C>, engage_from: S, waiting: S, attacking: S,
+                         disengage_to: S) -> StateMachine<S, C>
+    where S: Copy + Eq, C: Combatant {
+    machine
+        .transition(engage_from, waiting, |c| c.target_in_reach().is_some())
+        .transition(waiting, disengage_to, |c| c.target_in_reach().is_none())
+        .transition(waiting, attacking, |c| c.attack_cooldown().is_ready())
+        .transition(attacking, waiting, |c| c.swing_finished())
+        .on_enter(attacking, |c, _| {
+            c.attack_cooldown_mut().trigger();
+            *c.swing_target() = c.target_in_reach();
+            if !c.strike_on_hit_frame() {
+                c.strike();
+            }
+        })
+}
+
+
+// Shows the named texture when it was loaded and a flat colour otherwise, so a
+// missing asset never stops the game from drawing.
+fn paint<'a>(rect: &mut RectangleShape<'a>, textures: &'a TextureManager, sprite: Option<&str>,
+             fallback: graphics::Color) {
+    match sprite.and_then(|name| textures.get(name)) {
+        Some(texture) => {
+            rect.set_texture(texture, true);
+            rect.set_fill_color(graphics::Color::WHITE);
+        },
+        None => {
+            rect.disable_texture();
+            rect.set_fill_color(fallback);
+        },
+    }
+}
+
+fn unit_image<'a>(archetype: &UnitArchetype, x: f32, ground_y: f32, textures: &'a TextureManager) -> RectangleShape<'a> {
+    let [width, height] = archetype.size;
+    let [r, g, b] = archetype.color;
+    let mut rect = graphics::RectangleShape::new();
+    rect.set_size(system::Vector2f::new(width, height));
+    rect.set_origin(system::Vector2f::new(width / 2.0, height));
+    paint(&mut rect, textures, archetype.sprite.as_deref(), graphics::Color::rgb(r, g, b));
+    rect.set_position(system::Vector2f::new(x, ground_y));
+    rect
+}
+
+
+pub trait Entity<'this, T: Copy + Eq + AnimationState> : Damageable + GeoInfo + Combatant + StateMachineOwner<T> {
+    fn image(&mut self) -> &mut RectangleShape<'this>;
+
+    fn animator_mut(&mut self) -> &mut Animator;
+
+    // Name of the archetype the unit was spawned from.
+    fn archetype(&self) -> &str;
+
+    fn current_state(&self) -> T { self.state_machine().current() }
+
+    fn physical_states(&mut self) -> &mut PhysicalStates;
+
+    // Picks up edited archetype data on a live unit, leaving its position, state
+    // and share of HP alone.
+    fn apply_archetype(&mut self, archetype: &UnitArchetype, settings: &Settings, textures: &'this TextureManager) {
+        let x = self.get_position().x;
+        *self.image() = unit_image(archetype, x, settings.ground_pos_y, textures);
+        self.animator_mut().set_animation(archetype.animation.clone());
+        self.physical_states().walk_speed = archetype.walk_speed;
+        self.physical_states().friction = archetype.friction;
+        self.attack_cooldown_mut().set_duration(archetype.stats.attack_interval);
+        self.fight_status_mut().set_stats(archetype.stats.clone());
+    }
 
     fn move_(&mut self, vec: Vector2f, dt: f32) {
         self.image().move_(vec * system::Vector2f::new(dt * 60.0, dt * 60.0));
     }
 
-    fn rival_coming_state_changer(&mut self);
-    fn entity_behaviour_control(&mut self, dt: f32, rival_pos_list: &HashMap<u32, Vector2f>);
     fn velocity_update(&mut self, dt: f32) {
         if self.physical_states().velocity.abs() < 0.001 {
             self.physical_states().velocity = 0.0;
@@ -98,92 +300,161 @@ pub trait Entity<'this, T> : Damageable + GeoInfo {
         }
     }
 
-    fn position_check(&mut self) {
+    // Escaping units are left where they are; the world removes them once
+    // `is_off_map` says they are gone.
+    fn position_check(&mut self, policy: BoundaryPolicy, world_width: f32) {
+        let half_width = self.get_size().x / 2.0;
         let Vector2f {x, y} = self.image().position();
-        if x < 0.0 {
-            self.image().set_position(system::Vector2f::new(WINDOW_WIDTH as f32 + x, y));
-        }
-        if x > WINDOW_WIDTH as f32 && self.is_player() {
-            self.image().set_position(system::Vector2f::new(x - WINDOW_WIDTH as f32, y));
+        match policy {
+            BoundaryPolicy::Wrap => {
+                if x < 0.0 {
+                    self.image().set_position(system::Vector2f::new(world_width + x, y));
+                }
+                if x > world_width {
+                    self.image().set_position(system::Vector2f::new(x - world_width, y));
+                }
+            },
+            BoundaryPolicy::Clamp => {
+                let inside = x.clamp(half_width, (world_width - half_width).max(half_width));
+                if (inside - x).abs() > f32::EPSILON {
+                    self.image().set_position(system::Vector2f::new(inside, y));
+                    self.physical_states().velocity = 0.0;
+                }
+            },
+            BoundaryPolicy::Escape => {},
         }
     }
 
-    fn state_changer(&mut self, rival_coming: &Option<EnemyComing>) {}
+    fn is_off_map(&self, world_width: f32) -> bool {
+        let half_width = self.get_size().x / 2.0;
+        let x = self.get_position().x;
+        x + half_width < 0.0 || x - half_width > world_width
+    }
 
-    fn update(&mut self, dt: f32, pos_list: &mut HashMap<u32, Vector2f>, rival_coming: &Option<EnemyComing>,
-              rival_pos_list: &HashMap<u32, Vector2f>) {
+    fn sense(&mut self, rival_pos_list: &BTreeMap<Handle, Vector2f>) {
+        let target = self.generate_target_to_attack(rival_pos_list);
+        self.set_target_in_reach(target);
+    }
 
-        if let Some(enemies_coming_dir) = rival_coming {
-            match self.rival_dir() {
-                None => {
-                    self.set_rival_dir(Some((*enemies_coming_dir).clone()));
-                },
-                _ => {},
-            }
-            self.rival_coming_state_changer();
-        }
-        self.state_changer(rival_coming);
-        self.entity_behaviour_control(dt, &rival_pos_list);
-        self.velocity_update(dt);
-        self.position_check();
-        pos_list.insert(self.get_id(), self.get_position());
+    fn update(&mut self, ctx: &mut StateContext<'_>, pos_list: &mut BTreeMap<Handle, Vector2f>,
+              rival_pos_list: &BTreeMap<Handle, Vector2f>) {
+        self.attack_cooldown_mut().tick(ctx.dt);
+        self.sense(rival_pos_list);
+        state_machine::update(self, ctx);
+        self.animate(ctx.dt);
+        self.velocity_update(ctx.dt);
+        self.position_check(ctx.settings.boundary_policy, ctx.settings.world_width);
+        pos_list.insert(self.get_handle(), self.get_position());
     }
 
-    fn get_attack_target(&mut self) -> Option<(u32, f32)> {
-        if self.attack_timer().elapsed_time().as_seconds() > 1.5 {
-            return (*self.attack_target()).clone();
+    // Plays the clip of the current state, turns the sprite the way the unit is
+    // moving (sheets face right) and lands a pending strike on the hit frame.
+    fn animate(&mut self, dt: f32) {
+        let clip = self.current_state().clip();
+        let velocity = self.physical_states().velocity;
+        let animator = self.animator_mut();
+        animator.play(clip);
+        animator.face(velocity);
+        let event = animator.update(dt);
+        let frame = animator.texture_rect();
+        let flip = if animator.is_facing_left() { -1.0 } else { 1.0 };
+
+        if let Some(frame) = frame {
+            self.image().set_texture_rect(&frame);
+        }
+        self.image().set_scale(system::Vector2f::new(flip, 1.0));
+        if event == Some(AnimationEvent::Hit) {
+            self.strike();
         }
-        return None;
     }
 
-    fn generate_target_to_attack(&mut self, rival_pos_list: &HashMap<u32, Vector2f>) -> Option<u32>;
+    // Whether something centred at `pos` and `half_width` wide on each side is within attack range.
+    fn in_reach(&self, pos: Vector2f, half_width: f32) -> bool {
+        let gap = (pos.x - self.get_position().x).abs() - self.get_size().x / 2.0 - half_width;
+        gap <= self.stats().range
+    }
+
+    fn generate_target_to_attack(&self, rival_pos_list: &BTreeMap<Handle, Vector2f>) -> Option<Handle>;
 }
 
 
 pub struct Human<'a> {
     pub image: graphics::RectangleShape<'a>,
-    id: u32,
-    current_state: HumanState,
-    state_timer: system::Clock,
+    handle: Handle,
+    archetype: String,
+    state_machine: StateMachine<HumanState, Human<'a>>,
     physical_states: PhysicalStates,
+    animator: Animator,
     fight_status: EntityFightStatus,
-    attack_timer: system::Clock,
-    attack_target: Option<(u32, f32)>,
-    enemy_dir: Option<EnemyComing>,
+    attack_cooldown: Cooldown,
+    target_in_reach: Option<Handle>,
+    attack_target: Option<(Handle, f32)>,
+    swing_target: Option<Handle>,
+    // Where the fight this unit was sent to is.
+    front: Option<f32>,
 }
 
 impl<'a> Human<'a> {
 
-    pub fn new(id: u32) -> Human<'a> {
-        let mut rect = graphics::RectangleShape::new();
-        rect.set_size(system::Vector2f::new(HUMANS_HEIGHT - 20.0, HUMANS_HEIGHT));
-        rect.set_origin(system::Vector2f::new(rect.size().x / 2.0, rect.size().y));
-        rect.set_fill_color(graphics::Color::GREEN);
-        rect.set_position(system::Vector2f::new(30.0 + rect.size().x / 2.0, GROUND_POS_Y));
-        let mut timer = system::Clock::default();
-        timer.restart();
-        let mut attack_timer = system::Clock::default();
-        attack_timer.restart();
+    pub fn new(handle: Handle, name: &str, archetype: &UnitArchetype, settings: &Settings,
+               textures: &'a TextureManager) -> Human<'a> {
+        let stats = archetype.stats.clone();
         Human {
-            image: rect,
-            current_state: HumanState::Idle,
-            state_timer: timer,
-            id,
-            attack_timer,
-            physical_states: PhysicalStates{
-                velocity: 0.0,
-                friction: 0.2,
-            },
-            fight_status: EntityFightStatus{
-                attack_damage: 20.0,
-                armor: 0.0,
-                is_infant: None,
-                hp: HUMANS_MAX_HP,
-            },
+            image: unit_image(archetype, settings.world_width / 2.0, settings.ground_pos_y, textures),
+            state_machine: Human::behaviour(),
+            handle,
+            archetype: name.to_string(),
+            attack_cooldown: Cooldown::new(stats.attack_interval),
+            physical_states: PhysicalStates::new(archetype),
+            animator: Animator::new(archetype.animation.clone()),
+            fight_status: EntityFightStatus::new(stats),
+            target_in_reach: None,
             attack_target: None,
-            enemy_dir: None,
+            swing_target: None,
+            front: None,
         }
     }
+
+    fn behaviour() -> StateMachine<HumanState, Human<'a>> {
+        let machine = StateMachine::<_, Human<'a>>::new(HumanState::Idle)
+            .transition(HumanState::Idle, HumanState::Running, |h| h.front.is_some())
+            .transition(HumanState::Walking, HumanState::Running, |h| h.front.is_some())
+            .transition(HumanState::Idle, HumanState::Walking, |h| h.state_machine.time_in_state() > 3.0)
+            .transition(HumanState::Walking, HumanState::Idle, |h| h.state_machine.time_in_state() > 1.5)
+            .transition(HumanState::Running, HumanState::Walking, |h| h.front.is_none())
+            .on_enter(HumanState::Walking, Human::start_wandering)
+            .on_update(HumanState::Walking, |h, ctx| {
+                h.move_(system::Vector2f::new(h.physical_states.velocity, 0.0), ctx.dt);
+            })
+            .on_update(HumanState::Running, Human::run_to_front);
+        attack_loop(machine, HumanState::Running, HumanState::AttackWaiting, HumanState::Attacking,
+                    HumanState::Running)
+    }
+
+    // The x this unit should run to, or `None` to stand down.
+    pub fn defend(&mut self, front: Option<f32>) {
+        self.front = front;
+    }
+
+    fn start_wandering(&mut self, ctx: &mut StateContext<'_>) {
+        let speed = self.physical_states.walk_speed * ctx.settings.humans_idle_walk_speed_factor;
+        if ctx.rng.gen::<f32>() < 0.5 {
+            self.physical_states.velocity = speed;
+        } else {
+            self.physical_states.velocity = -speed;
+        }
+    }
+
+    // Heads for the front from whichever side of it the unit is on.
+    fn run_to_front(&mut self, ctx: &mut StateContext<'_>) {
+        let x = self.get_position().x;
+        self.physical_states.velocity = match self.front {
+            Some(front) if front < x => -self.physical_states.walk_speed,
+            Some(front) if front > x => self.physical_states.walk_speed,
+            _ => 0.0,
+        };
+        self.move_(Vector2f::new(self.physical_states.velocity, 0.0), ctx.dt);
+    }
 }
 
 impl GeoInfo for Human<'_> {
@@ -195,184 +466,118 @@ impl GeoInfo for Human<'_> {
     }
     fn is_human(&self) -> bool { true }
     fn geoinfo_get_hp(&self) -> f32 { self.get_hp() }
+    fn geoinfo_get_max_hp(&self) -> f32 { self.stats().max_hp }
 }
 
 impl DrawHP for Human<'_> {
 }
 
 impl Damageable for Human<'_> {
-    fn get_id(&self) -> u32 { self.id }
-    fn get_hp(&self) -> f32 { self.fight_status.hp }
-    fn fight_status(&mut self) -> &mut EntityFightStatus { &mut self.fight_status }
+    fn get_handle(&self) -> Handle { self.handle }
+    fn fight_status(&self) -> &EntityFightStatus { &self.fight_status }
+    fn fight_status_mut(&mut self) -> &mut EntityFightStatus { &mut self.fight_status }
 }
 
-impl<'a> Entity<'a, HumanState> for Human<'a> {
-    fn image(&mut self) -> &mut RectangleShape<'a> { &mut self.image }
-
-    fn is_player(&self) -> bool { true }
-
-    fn current_state(&self) -> &HumanState { &self.current_state }
-    fn set_current_state(&mut self, state: HumanState) { self.current_state = state }
-
-    fn physical_states(&mut self) -> &mut PhysicalStates { &mut self.physical_states }
+impl Combatant for Human<'_> {
+    fn attack_cooldown(&self) -> &Cooldown { &self.attack_cooldown }
+    fn attack_cooldown_mut(&mut self) -> &mut Cooldown { &mut self.attack_cooldown }
 
-    fn state_timer(&mut self) -> &mut system::Clock { &mut self.state_timer }
-    fn attack_timer(&mut self) -> &mut system::Clock { &mut self.attack_timer }
+    fn target_in_reach(&self) -> Option<Handle> { self.target_in_reach }
+    fn set_target_in_reach(&mut self, target: Option<Handle>) { self.target_in_reach = target }
 
-    fn attack_target(&mut self) -> &mut Option<(u32, f32)> { &mut self.attack_target }
-    fn set_attack_target(&mut self, target: Option<(u32, f32)>) { self.attack_target = target }
+    fn attack_target(&mut self) -> &mut Option<(Handle, f32)> { &mut self.attack_target }
+    fn swing_target(&mut self) -> &mut Option<Handle> { &mut self.swing_target }
 
-    fn rival_dir(&self) -> &Option<EnemyComing> { &self.enemy_dir }
-    fn set_rival_dir(&mut self, dir: Option<EnemyComing>) { self.enemy_dir = dir }
+    fn strike_on_hit_frame(&self) -> bool { self.animator.has_hit_frame(HumanState::Attacking.clip()) }
+    fn swing_finished(&self) -> bool { !self.animator.is_busy() }
+}
 
-    fn rival_coming_state_changer(&mut self) {
-        match self.current_state() {
-            HumanState::Idle | HumanState::Walking => {
-                self.set_current_state(HumanState::Running);
-            },
-            _ => {},
-        }
-    }
+impl<'a> StateMachineOwner<HumanState> for Human<'a> {
+    fn state_machine(&self) -> &StateMachine<HumanState, Human<'a>> { &self.state_machine }
+    fn state_machine_mut(&mut self) -> &mut StateMachine<HumanState, Human<'a>> { &mut self.state_machine }
+}
 
-    fn state_changer(&mut self, rival_coming: &Option<EnemyComing>) {
-        match rival_coming {
-            None => {
-                if self.current_state == HumanState::AttackWaiting {
-                    self.enemy_dir = None;
-                    self.attack_target = None;
-                    self.current_state = HumanState::Walking;
-                }
-            },
-            _ => {},
-        }
-    }
+impl<'a> Entity<'a, HumanState> for Human<'a> {
+    fn image(&mut self) -> &mut RectangleShape<'a> { &mut self.image }
 
-    fn entity_behaviour_control(&mut self, dt: f32, rival_pos_list: &HashMap<u32, Vector2f>) {
+    fn animator_mut(&mut self) -> &mut Animator { &mut self.animator }
 
-        match self.current_state {
-            HumanState::Idle => {
-                if self.state_timer.elapsed_time().as_seconds() > 3.0 {
-                    self.state_timer.restart();
-                    self.current_state = HumanState::Walking;
+    fn archetype(&self) -> &str { &self.archetype }
 
-                    let mut rand_gen = rand::thread_rng();
-                    let unif = rand::distributions::Uniform::new(0.0, 1.0);
-                    let speed = HUMANS_WALK_SPEED * HUMANS_IDLE_WALK_SPEED_FACTOR;
-                    if rand_gen.sample(&unif) < 0.5 {
-                        self.physical_states.velocity = speed;
-                    } else {
-                        self.physical_states.velocity = -speed;
-                    }
-                }
-            },
-            HumanState::Walking => {
-                self.move_(system::Vector2f::new(self.physical_states.velocity, 0.0), dt);
-                if self.state_timer.elapsed_time().as_seconds() > 1.5 {
-                    self.state_timer.restart();
-                    self.current_state = HumanState::Idle;
-                }
-            },
-            HumanState::Running => {
-                self.physical_states.velocity = HUMANS_WALK_SPEED;
-                let mut velocity = Vector2f::new(self.physical_states.velocity, 0.0);
-                match self.enemy_dir {
-                    Some(EnemyComing::RIGHT) => {
-
-                    },
-                    Some(EnemyComing::LEFT) => {
-                        velocity = Vector2f::new(- self.physical_states.velocity, 0.0);
-                    },
-                    None => {
-                        self.current_state = HumanState::Walking;
-                        self.attack_target = None;
-                        self.enemy_dir = None;
-                    },
-                }
-                self.move_(velocity, dt);
-                if let Some(enemy_id) = self.generate_target_to_attack(&rival_pos_list) {
-                    self.current_state = HumanState::Attacking;
-                    self.attack_target = Some((enemy_id, self.fight_status.attack_damage));
-                }
-            },
-            HumanState::Attacking => {
-                if self.attack_timer.elapsed_time().as_seconds() > 1.5 {
-                    if let Some((id, dmg)) = self.attack_target {
-                        self.attack_target = Some((id, self.fight_status.attack_damage));
-                        self.attack_timer.restart();
-                    }
-                } else {
-                    self.current_state = HumanState::AttackWaiting;
-                }
-            },
-            HumanState::AttackWaiting => {
-                if self.attack_timer.elapsed_time().as_seconds() > 1.5 {
-                    self.current_state = HumanState::Attacking;
-                }
-            },
-        }
-    }
+    fn physical_states(&mut self) -> &mut PhysicalStates { &mut self.physical_states }
 
-    fn generate_target_to_attack(&mut self, rival_pos_list: &HashMap<u32, Vector2f>) -> Option<u32> {
-        for (rival_id, rival_pos) in rival_pos_list.iter() {
-            if self.get_position().x + self.get_size().x / 2.0 > rival_pos.x - self.get_size().x / 2.0 {
-                return Some(*rival_id);
+    fn generate_target_to_attack(&self, rival_pos_list: &BTreeMap<Handle, Vector2f>) -> Option<Handle> {
+        for (rival, rival_pos) in rival_pos_list.iter() {
+            if self.in_reach(*rival_pos, self.get_size().x / 2.0) {
+                return Some(*rival);
             }
         }
-        return None;
+        None
     }
 }
 
 
 pub struct Enemy<'a> {
     pub image: graphics::RectangleShape<'a>,
-    id: u32,
-    current_state: EnemyState,
-    state_timer: system::Clock,
+    handle: Handle,
+    archetype: String,
+    state_machine: StateMachine<EnemyState, Enemy<'a>>,
     fight_status: EntityFightStatus,
-    velocity: f32,
-    attack_target: Option<(u32, f32)>,
-    attack_timer: system::Clock,
+    target_in_reach: Option<Handle>,
+    attack_target: Option<(Handle, f32)>,
+    swing_target: Option<Handle>,
+    attack_cooldown: Cooldown,
     physical_states: PhysicalStates,
-    rival_direction: Option<EnemyComing>,
-    building_pos_list: Vec<Vector2f>,
+    animator: Animator,
+    approach: EnemyComing,
+    building_pos_list: BTreeMap<Handle, Footprint>,
 }
 
 impl<'a> Enemy<'a> {
 
-    pub fn new(id: u32) -> Enemy<'a> {
-        let mut rect = graphics::RectangleShape::new();
-        rect.set_size(system::Vector2f::new(HUMANS_HEIGHT - 20.0, HUMANS_HEIGHT));
-        rect.set_origin(system::Vector2f::new(rect.size().x / 2.0, rect.size().y));
-        rect.set_fill_color(graphics::Color::RED);
-        rect.set_position(system::Vector2f::new(1300.0, GROUND_POS_Y));
-        let mut timer = system::Clock::default();
-        timer.restart();
-        let mut attack_timer = system::Clock::default();
-        attack_timer.restart();
+    // Enemies start at the edge of the world they approach from.
+    pub fn new(handle: Handle, name: &str, archetype: &UnitArchetype, settings: &Settings,
+               approach: EnemyComing, textures: &'a TextureManager) -> Enemy<'a> {
+        let stats = archetype.stats.clone();
+        let x = match approach {
+            EnemyComing::LEFT => archetype.size[0] / 2.0,
+            EnemyComing::RIGHT => settings.world_width - archetype.size[0] / 2.0,
+        };
         Enemy {
-            image: rect,
-            current_state: EnemyState::Running,
-            state_timer: timer,
-            attack_timer,
-            id,
-            velocity: 0.0,
+            image: unit_image(archetype, x, settings.ground_pos_y, textures),
+            state_machine: Enemy::behaviour(),
+            attack_cooldown: Cooldown::new(stats.attack_interval),
+            handle,
+            archetype: name.to_string(),
+            target_in_reach: None,
             attack_target: None,
-            fight_status: EntityFightStatus{
-                attack_damage: 20.0,
-                armor: 0.0,
-                is_infant: None,
-                hp: HUMANS_MAX_HP,
-            },
-            physical_states: PhysicalStates{
-                velocity: 0.0,
-                friction: 0.2,
-            },
-            rival_direction: None,
-            building_pos_list: Vec::new(),
+            swing_target: None,
+            fight_status: EntityFightStatus::new(stats),
+            physical_states: PhysicalStates::new(archetype),
+            animator: Animator::new(archetype.animation.clone()),
+            approach,
+            building_pos_list: BTreeMap::new(),
         }
     }
 
-    pub fn update_building_pos_list(&mut self, list: Vec<Vector2f>) { self.building_pos_list = list; }
+    fn behaviour() -> StateMachine<EnemyState, Enemy<'a>> {
+        let machine = StateMachine::new(EnemyState::Running)
+            .on_update(EnemyState::Running, Enemy::advance);
+        attack_loop(machine, EnemyState::Running, EnemyState::AttackWaiting, EnemyState::Attacking,
+                    EnemyState::Running)
+    }
+
+    fn advance(&mut self, ctx: &mut StateContext<'_>) {
+        self.physical_states.velocity = match self.approach {
+            EnemyComing::LEFT => self.physical_states.walk_speed,
+            EnemyComing::RIGHT => -self.physical_states.walk_speed,
+        };
+        self.move_(system::Vector2f::new(self.physical_states.velocity, 0.0), ctx.dt);
+    }
+
+    pub fn update_building_pos_list(&mut self, list: &BTreeMap<Handle, Footprint>) {
+        self.building_pos_list.clone_from(list);
+    }
 }
 
 impl GeoInfo for Enemy<'_> {
@@ -380,84 +585,76 @@ impl GeoInfo for Enemy<'_> {
     fn get_size(&self) -> Vector2f { self.image.size() }
     fn is_human(&self) -> bool { true }
     fn geoinfo_get_hp(&self) -> f32 { self.get_hp() }
+    fn geoinfo_get_max_hp(&self) -> f32 { self.stats().max_hp }
 }
 
 impl DrawHP for Enemy<'_> {}
 
 impl Damageable for Enemy<'_> {
-    fn get_id(&self) -> u32 { self.id }
-    fn get_hp(&self) -> f32 { self.fight_status.hp }
-    fn fight_status(&mut self) -> &mut EntityFightStatus { &mut self.fight_status }
+    fn get_handle(&self) -> Handle { self.handle }
+    fn fight_status(&self) -> &EntityFightStatus { &self.fight_status }
+    fn fight_status_mut(&mut self) -> &mut EntityFightStatus { &mut self.fight_status }
 }
 
-impl<'a> Entity<'a, EnemyState> for Enemy<'a> {
-    fn image(&mut self) -> &mut RectangleShape<'a> { &mut self.image }
+impl Combatant for Enemy<'_> {
+    fn attack_cooldown(&self) -> &Cooldown { &self.attack_cooldown }
+    fn attack_cooldown_mut(&mut self) -> &mut Cooldown { &mut self.attack_cooldown }
 
-    fn is_player(&self) -> bool { false }
+    fn target_in_reach(&self) -> Option<Handle> { self.target_in_reach }
+    fn set_target_in_reach(&mut self, target: Option<Handle>) { self.target_in_reach = target; }
 
-    fn current_state(&self) -> &EnemyState { &self.current_state }
-    fn set_current_state(&mut self, state: EnemyState) { self.current_state = state; }
+    fn attack_target(&mut self) -> &mut Option<(Handle, f32)> { &mut self.attack_target }
+    fn swing_target(&mut self) -> &mut Option<Handle> { &mut self.swing_target }
 
-    fn physical_states(&mut self) -> &mut PhysicalStates { &mut self.physical_states }
+    fn strike_on_hit_frame(&self) -> bool { self.animator.has_hit_frame(EnemyState::Attacking.clip()) }
+    fn swing_finished(&self) -> bool { !self.animator.is_busy() }
+}
 
-    fn state_timer(&mut self) -> &mut system::Clock { &mut self.state_timer }
-    fn attack_timer(&mut self) -> &mut system::Clock { &mut self.attack_timer }
+impl<'a> StateMachineOwner<EnemyState> for Enemy<'a> {
+    fn state_machine(&self) -> &StateMachine<EnemyState, Enemy<'a>> { &self.state_machine }
+    fn state_machine_mut(&mut self) -> &mut StateMachine<EnemyState, Enemy<'a>> { &mut self.state_machine }
+}
 
-    fn attack_target(&mut self) -> &mut Option<(u32, f32)> { &mut self.attack_target }
-    fn set_attack_target(&mut self, target: Option<(u32, f32)>) { self.attack_target = target; }
+impl<'a> Entity<'a, EnemyState> for Enemy<'a> {
+    fn image(&mut self) -> &mut RectangleShape<'a> { &mut self.image }
 
-    fn rival_dir(&self) -> &Option<EnemyComing> { &self.rival_direction }
-    fn set_rival_dir(&mut self, dir: Option<EnemyComing>) { self.rival_direction = dir; }
+    fn animator_mut(&mut self) -> &mut Animator { &mut self.animator }
 
-    fn rival_coming_state_changer(&mut self) {
+    fn archetype(&self) -> &str { &self.archetype }
 
-    }
+    fn physical_states(&mut self) -> &mut PhysicalStates { &mut self.physical_states }
 
-    fn entity_behaviour_control(&mut self, dt: f32, rival_pos_list: &HashMap<u32, Vector2f>) {
-        match self.current_state {
-            EnemyState::Running => {
-                if self.get_position().x > WINDOW_WIDTH as f32 / 2.0 {
-                    self.move_(system::Vector2f::new(-ENEMY_WALK_SPEED, 0.0), dt);
-                } else {
-                    self.move_(system::Vector2f::new(ENEMY_WALK_SPEED, 0.0), dt);
-                }
-                if let Some(attack_target_id) = self.generate_target_to_attack(rival_pos_list) {
-                    self.current_state = EnemyState::Attacking;
-                    self.attack_target = Some((attack_target_id, self.fight_status.attack_damage));
-                }
-            },
-            EnemyState::Attacking => {
-                if self.attack_timer.elapsed_time().as_seconds() > 1.5 {
-                    if let Some((id, dmg)) = self.attack_target {
-                        self.attack_target = Some((id, self.fight_status.attack_damage));
-                        self.attack_timer.restart();
-                    }
-                } else {
-                    self.current_state = EnemyState::AttackWaiting;
-                }
-            },
-            EnemyState::AttackWaiting => {
-                if self.attack_timer.elapsed_time().as_seconds() > 1.5 {
-                    self.current_state = EnemyState::Attacking;
-                }
+    fn generate_target_to_attack(&self, rival_pos_list: &BTreeMap<Handle, Vector2f>) -> Option<Handle> {
+        for (human, human_pos) in rival_pos_list.iter() {
+            if self.in_reach(*human_pos, self.get_size().x / 2.0) {
+                return Some(*human);
             }
         }
-    }
-
-    fn generate_target_to_attack(&mut self, rival_pos_list: &HashMap<u32, Vector2f>) -> Option<u32> {
-        if self.attack_timer.elapsed_time().as_seconds() > 1.5 {
-            for (human_id, human_pos) in rival_pos_list.iter() {
-                if self.image.position().x - self.image.size().x / 2.0 < human_pos.x + self.image.size().x / 2.0 {
-                    return Some(*human_id);
-                }
-            }
-            for (building_id, building_pos) in self.building_pos_list.iter().enumerate() {
-                if self.image.position().x - self.image.size().x / 2.0 < building_pos.x + 50.0 {
-                    return Some(building_id as u32 + 100);
-                }
+        for (building, footprint) in self.building_pos_list.iter() {
+            if self.in_reach(footprint.pos, footprint.size.x / 2.0) {
+                return Some(*building);
             }
         }
-        return None;
+        None
+    }
+}
+
+
+
+
+// Where a building stands (bottom centre) and how much ground it covers; enough
+// for placing new buildings and for enemies to tell whether one is in reach.
+#[derive(Clone, Copy, Debug)]
+pub struct Footprint {
+    pub pos: Vector2f,
+    pub size: Vector2f,
+}
+
+impl Footprint {
+
+    // Empty ground between the two; negative when they overlap.
+    pub fn gap(&self, other: &Footprint) -> f32 {
+        (self.pos.x - other.pos.x).abs() - (self.size.x + other.size.x) / 2.0
     }
 }
 
@@ -466,51 +663,49 @@ pub struct Building<'a> {
     pub image: graphics::RectangleShape<'a>,
     fight_status: EntityFightStatus,
     pub building_type: BuildingType,
-    id: u32,
+    handle: Handle,
 }
 
 impl<'a> Building<'a> {
 
-    pub fn new(building_type: BuildingType) -> Building<'a> {
+    pub fn new(building_type: BuildingType, handle: Handle, settings: &Settings,
+               textures: &'a TextureManager) -> Building<'a> {
         let mut rect = graphics::RectangleShape::new();
-        rect.set_size(system::Vector2f::new(100.0, 100.0));
-        rect.set_origin(system::Vector2f::new(rect.size().x / 2.0, rect.size().y));
-        rect.set_fill_color(graphics::Color::BLUE);
-        let mut hp: f32 = 100.0;
-        match building_type {
-            BuildingType::Base => {
-                hp = BUILDING_BASE_MAX_HP;
-                rect.set_position(system::Vector2f::new(20.0 + rect.size().x / 2.0, GROUND_POS_Y));
-            },
-            BuildingType::Others => {
-                hp = BUILDING_OTHERS_MAX_HP;
-                rect.set_position(system::Vector2f::new(20.0 + rect.size().x / 2.0, GROUND_POS_Y));
-            },
-        }
-        Building {
+        paint(&mut rect, textures, Some(building_type.sprite()), graphics::Color::BLUE);
+        rect.set_position(system::Vector2f::new(settings.world_width / 2.0, settings.ground_pos_y));
+        let mut building = Building {
             image: rect,
-            fight_status: EntityFightStatus {
-                attack_damage: 50.0,
-                armor: 0.0,
-                // 是否是近战
-                is_infant: None,
-                hp: 500.0,
-            },
+            fight_status: EntityFightStatus::new(Stats::building(building_type, settings)),
             building_type,
-            id: 100,
-        }
+            handle,
+        };
+        building.resize(building_type.size(settings));
+        building
     }
 
     pub fn get_position(&self) -> system::Vector2f {
         self.image.position()
     }
 
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        self.fight_status.set_stats(Stats::building(self.building_type, settings));
+        self.resize(self.building_type.size(settings));
+        let x = self.image.position().x;
+        self.image.set_position(system::Vector2f::new(x, settings.ground_pos_y));
+    }
+
     pub fn set_position(&mut self, pos: system::Vector2f) {
         self.image.set_position(pos);
     }
 
+    // Keeps the building standing on the same spot of ground.
     pub fn resize(&mut self, size: system::Vector2f) {
         self.image.set_size(size);
+        self.image.set_origin(system::Vector2f::new(size.x / 2.0, size.y));
+    }
+
+    pub fn footprint(&self) -> Footprint {
+        Footprint { pos: self.image.position(), size: self.image.size() }
     }
 }
 
@@ -523,16 +718,17 @@ impl GeoInfo for Building<'_> {
         self.image.size()
     }
     fn is_human(&self) -> bool { false }
-    fn geoinfo_get_hp(&self) -> f32 { self.fight_status.hp }
+    fn geoinfo_get_hp(&self) -> f32 { self.get_hp() }
+    fn geoinfo_get_max_hp(&self) -> f32 { self.stats().max_hp }
 }
 
 impl DrawHP for Building<'_> {
 }
 
 impl Damageable for Building<'_> {
-    fn get_id(&self) -> u32 { self.id }
-    fn get_hp(&self) -> f32 { self.fight_status.hp }
-    fn fight_status(&mut self) -> &mut EntityFightStatus { &mut self.fight_status }
+    fn get_handle(&self) -> Handle { self.handle }
+    fn fight_status(&self) -> &EntityFightStatus { &self.fight_status }
+    fn fight_status_mut(&mut self) -> &mut EntityFightStatus { &mut self.fight_status }
 }
 
 
@@ -541,11 +737,11 @@ pub struct BaseGround<'a> {
 }
 
 impl<'a> BaseGround<'a> {
-    pub fn new() -> BaseGround<'a> {
+    pub fn new(settings: &Settings, textures: &'a TextureManager) -> BaseGround<'a> {
         let mut rect = graphics::RectangleShape::new();
-        rect.set_size(system::Vector2f::new(WINDOW_WIDTH as f32, GROUND_HEIGHT));
-        rect.set_fill_color(graphics::Color::MAGENTA);
-        rect.set_position(system::Vector2f::new(0.0, GROUND_POS_Y));
+        rect.set_size(system::Vector2f::new(settings.world_width, settings.ground_height));
+        paint(&mut rect, textures, Some("ground.png"), graphics::Color::MAGENTA);
+        rect.set_position(system::Vector2f::new(0.0, settings.ground_pos_y));
         BaseGround {
             image: rect,
         }
@@ -0,0 +1,80 @@
+use super::handle::{Handle, Team};
+use super::entity::BuildingType;
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CombatEvent {
+    AttackStarted { attacker: Handle, target: Handle },
+    DamageDealt { source: Handle, target: Handle, amount: f32 },
+    EntityDied { handle: Handle },
+    UnitEscaped { handle: Handle, lives_left: u32 },
+    BuildingDestroyed { handle: Handle, building_type: BuildingType },
+    WaveCleared { wave: u32 },
+}
+
+
+type Subscriber<'a> = Box<dyn FnMut(&CombatEvent) + 'a>;
+
+
+// Events raised during a tick are held back and handed to subscribers together
+// once the tick has been resolved, so listeners always see a consistent world.
+pub struct EventBus<'a> {
+    pending: Vec<CombatEvent>,
+    subscribers: Vec<Subscriber<'a>>,
+}
+
+impl<'a> EventBus<'a> {
+
+    pub fn new() -> EventBus<'a> {
+        EventBus {
+            pending: Vec::new(),
+            subscribers: Vec::new(),
+        }
+    }
+
+    pub fn subscribe<F: FnMut(&CombatEvent) + 'a>(&mut self, subscriber: F) {
+        self.subscribers.push(Box::new(subscriber));
+    }
+
+    pub fn emit(&mut self, event: CombatEvent) {
+        self.pending.push(event);
+    }
+
+    pub fn publish(&mut self) {
+        for subscriber in self.subscribers.iter_mut() {
+            for event in self.pending.iter() {
+                subscriber(event);
+            }
+        }
+        self.pending.clear();
+    }
+}
+
+
+#[derive(Clone, Debug, Default)]
+pub struct BattleStats {
+    pub enemies_defeated: u32,
+    pub units_lost: u32,
+    pub enemies_leaked: u32,
+    pub damage_dealt: f32,
+}
+
+impl BattleStats {
+
+    pub fn record(&mut self, event: &CombatEvent) {
+        match event {
+            CombatEvent::EntityDied { handle } => match handle.team() {
+                Team::Enemy => self.enemies_defeated += 1,
+                Team::Player => self.units_lost += 1,
+            },
+            CombatEvent::UnitEscaped { handle, .. } => match handle.team() {
+                Team::Enemy => self.enemies_leaked += 1,
+                Team::Player => self.units_lost += 1,
+            },
+            CombatEvent::DamageDealt { source, amount, .. } if source.team() == Team::Player => {
+                self.damage_dealt += amount;
+            },
+            _ => {},
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use super::graphics::Font;
+use super::sfml::system::SfBox;
+use super::assets::{AssetError, AssetLocator};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+
+// Fonts are loaded once at startup, like textures, and named by file name:
+// "SourceCodePro.ttf". Text elements borrow them for the whole game.
+pub struct FontManager {
+    dir: String,
+    // SFML reads glyphs from the file data on demand, so each font keeps its
+    // bytes alive next to it; the font is dropped first.
+    dict: HashMap<String, (SfBox<Font>, Vec<u8>)>,
+    broken: HashSet<String>,
+}
+
+impl FontManager {
+
+    pub fn load(assets: &AssetLocator, dir: &str) -> FontManager {
+        let dir = dir.trim_end_matches('/').to_string();
+        let prefix = format!("{}/", dir);
+        let mut manager = FontManager { dir, dict: HashMap::new(), broken: HashSet::new() };
+        for path in assets.list(&manager.dir) {
+            let name = &path[prefix.len()..];
+            let is_font = name.ends_with(".ttf") || name.ends_with(".otf");
+            if !is_font || name.contains('/') {
+                continue;
+            }
+            let data = assets.read(&path).map(|data| data.into_owned()).unwrap_or_default();
+            match Font::from_memory(&data) {
+                Some(font) => {
+                    manager.dict.insert(name.to_string(), (font, data));
+                },
+                None => {
+                    manager.broken.insert(name.to_string());
+                },
+            }
+        }
+        manager
+    }
+
+    // Unlike a missing texture there is nothing to fall back to, so callers get
+    // an error saying whether the file is absent or unreadable.
+    pub fn require(&self, name: &str) -> Result<&Font, AssetError> {
+        match self.dict.get(name) {
+            Some((font, _)) => Ok(&**font),
+            None if self.broken.contains(name) => Err(AssetError::Invalid(PathBuf::from(format!("{}/{}", self.dir, name)))),
+            None => Err(AssetError::Missing(PathBuf::from(format!("{}/{}", self.dir, name)))),
+        }
+    }
+}
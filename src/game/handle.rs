@@ -0,0 +1,61 @@
+use super::serde::Deserialize;
+
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Ord, PartialOrd, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Team {
+    Player,
+    Enemy,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum EntityKind {
+    Unit,
+    Building,
+    // The source of damage dealt by a card rather than a unit.
+    Spell,
+}
+
+
+// Ids are unique across every kind and team, so a handle can be used as a key
+// or an attack target without knowing which list the entity lives in.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct Handle {
+    id: u32,
+    kind: EntityKind,
+    team: Team,
+}
+
+impl Handle {
+    pub fn team(&self) -> Team { self.team }
+}
+
+
+pub struct IdAllocator {
+    next_id: u32,
+}
+
+impl IdAllocator {
+
+    pub fn new() -> IdAllocator {
+        IdAllocator { next_id: 0 }
+    }
+
+    fn allocate(&mut self, kind: EntityKind, team: Team) -> Handle {
+        let id = self.next_id;
+        self.next_id += 1;
+        Handle { id, kind, team }
+    }
+
+    pub fn unit(&mut self, team: Team) -> Handle {
+        self.allocate(EntityKind::Unit, team)
+    }
+
+    pub fn building(&mut self, team: Team) -> Handle {
+        self.allocate(EntityKind::Building, team)
+    }
+
+    pub fn spell(&mut self, team: Team) -> Handle {
+        self.allocate(EntityKind::Spell, team)
+    }
+}
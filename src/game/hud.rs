@@ -0,0 +1,107 @@
+use super::graphics::{Color, Font, RenderWindow};
+use super::sfml::system::Vector2f;
+use super::ui::{Ui, WidgetId, Direction, PanelStyle, Align, GeoInfo};
+use super::world::World;
+use super::super::settings::{Settings, BoundaryPolicy};
+use std::fmt::Write;
+
+
+// Screen-space status bar along the top of the battle. The widgets are built
+// once; every frame only their text and fill change, formatted into one reused
+// buffer.
+pub struct Hud<'a> {
+    ui: Ui<'a, ()>,
+    base_bar: WidgetId,
+    base_hp: WidgetId,
+    wave: WidgetId,
+    units: WidgetId,
+    gold: WidgetId,
+    lives: WidgetId,
+    speed: WidgetId,
+    notice_box: WidgetId,
+    notice: WidgetId,
+    buffer: String,
+}
+
+impl<'a> Hud<'a> {
+
+    pub fn new(font: &'a Font) -> Hud<'a> {
+        let mut ui = Ui::new(font);
+        let style = PanelStyle { padding: 8.0, spacing: 24.0, background: Color::rgba(0, 0, 0, 140), align: Align::Center };
+        let bar = ui.panel(None, Direction::Horizontal, style);
+        ui.set_anchor(bar, Vector2f::new(10.0, 10.0), Vector2f::new(0.0, 0.0));
+
+        let base_style = PanelStyle { spacing: 8.0, align: Align::Center, ..PanelStyle::default() };
+        let base = ui.panel(Some(bar), Direction::Horizontal, base_style);
+        ui.label(Some(base), "Base", 18, Color::WHITE);
+        let base_bar = ui.progress_bar(Some(base), Vector2f::new(160.0, 14.0), Color::GREEN);
+        let base_hp = ui.label(Some(base), "", 18, Color::WHITE);
+
+        // Below it, why the last card could not be played.
+        let notice_style = PanelStyle { padding: 8.0, background: Color::rgba(0, 0, 0, 140), ..PanelStyle::default() };
+        let notice_box = ui.panel(None, Direction::Vertical, notice_style);
+        ui.set_anchor(notice_box, Vector2f::new(10.0, 56.0), Vector2f::new(0.0, 0.0));
+        let notice = ui.label(Some(notice_box), "", 16, Color::RED);
+        ui.set_visible(notice_box, false);
+
+        Hud {
+            notice_box,
+            notice,
+            base_bar,
+            base_hp,
+            wave: ui.label(Some(bar), "", 18, Color::WHITE),
+            units: ui.label(Some(bar), "", 18, Color::WHITE),
+            gold: ui.label(Some(bar), "", 18, Color::YELLOW),
+            lives: ui.label(Some(bar), "", 18, Color::WHITE),
+            speed: ui.label(Some(bar), "", 18, Color::WHITE),
+            ui,
+            buffer: String::new(),
+        }
+    }
+
+    pub fn update(&mut self, world: &World<'_>, settings: &Settings, speed: f32) {
+        let (hp, max_hp) = world.base().map_or((0.0, 0.0), |base| (base.geoinfo_get_hp(), base.geoinfo_get_max_hp()));
+        let ratio = if max_hp > 0.0 { hp / max_hp } else { 0.0 };
+        self.ui.set_progress(self.base_bar, ratio);
+        self.ui.set_color(self.base_bar, if ratio > 0.3 { Color::GREEN } else { Color::RED });
+        self.format(format_args!("{:.0}/{:.0}", hp.ceil(), max_hp));
+        self.ui.set_text(self.base_hp, &self.buffer);
+
+        match world.wave_countdown() {
+            Some(seconds) => self.format(format_args!("Wave {}/{} in {:.0}s", world.wave(), world.wave_count(),
+                                                      seconds.ceil())),
+            None => self.format(format_args!("Wave {}/{}", world.wave(), world.wave_count())),
+        }
+        self.ui.set_text(self.wave, &self.buffer);
+
+        self.format(format_args!("Defenders {}  Enemies {}", world.humans().len(), world.enemies().len()));
+        self.ui.set_text(self.units, &self.buffer);
+
+        self.format(format_args!("Gold {:.0}", world.gold().floor()));
+        self.ui.set_text(self.gold, &self.buffer);
+
+        // Lives only matter when enemies can get away.
+        self.ui.set_visible(self.lives, settings.boundary_policy == BoundaryPolicy::Escape);
+        self.format(format_args!("Lives {}", world.lives()));
+        self.ui.set_text(self.lives, &self.buffer);
+
+        self.format(format_args!("Speed x{}", speed));
+        self.ui.set_text(self.speed, &self.buffer);
+    }
+
+    pub fn set_notice(&mut self, notice: Option<&str>) {
+        self.ui.set_visible(self.notice_box, notice.is_some());
+        if let Some(notice) = notice {
+            self.ui.set_text(self.notice, notice);
+        }
+    }
+
+    fn format(&mut self, args: std::fmt::Arguments<'_>) {
+        self.buffer.clear();
+        let _ = self.buffer.write_fmt(args);
+    }
+
+    pub fn draw(&mut self, win: &mut RenderWindow) {
+        self.ui.draw(win);
+    }
+}
@@ -0,0 +1,86 @@
+// Asset archive format, shared with the `pack_assets` tool, so it only depends on std.
+//
+//   magic "CASTLEPK", entry count (u32 LE)
+//   per entry: name length (u32 LE), name (UTF-8, `/` separated), data length (u64 LE), data
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::io::{self, Write};
+use std::ops::Range;
+
+
+const MAGIC: &[u8; 8] = b"CASTLEPK";
+
+
+// Only the pack tool writes archives.
+#[allow(dead_code)]
+pub fn write_archive<W: Write>(out: &mut W, files: &[(String, Vec<u8>)]) -> io::Result<()> {
+    out.write_all(MAGIC)?;
+    out.write_all(&(files.len() as u32).to_le_bytes())?;
+    for (name, data) in files {
+        out.write_all(&(name.len() as u32).to_le_bytes())?;
+        out.write_all(name.as_bytes())?;
+        out.write_all(&(data.len() as u64).to_le_bytes())?;
+        out.write_all(data)?;
+    }
+    Ok(())
+}
+
+
+pub struct Archive {
+    data: Cow<'static, [u8]>,
+    entries: BTreeMap<String, Range<usize>>,
+}
+
+impl Archive {
+
+    pub fn parse(data: Cow<'static, [u8]>) -> io::Result<Archive> {
+        let mut entries = BTreeMap::new();
+        let mut cursor = Cursor { data: &data, pos: 0 };
+        if cursor.take(MAGIC.len())? != MAGIC {
+            return Err(invalid("not an asset archive"));
+        }
+        let count = u32::from_le_bytes(cursor.take(4)?.try_into().unwrap());
+        for _ in 0..count {
+            let name_len = u32::from_le_bytes(cursor.take(4)?.try_into().unwrap()) as usize;
+            let name = std::str::from_utf8(cursor.take(name_len)?)
+                .map_err(|_| invalid("entry name is not UTF-8"))?
+                .to_string();
+            let len = u64::from_le_bytes(cursor.take(8)?.try_into().unwrap()) as usize;
+            let start = cursor.pos;
+            cursor.take(len)?;
+            entries.insert(name, start..start + len);
+        }
+        Ok(Archive { data, entries })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.entries.get(name).map(|range| &self.data[range.clone()])
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+}
+
+
+struct Cursor<'d> {
+    data: &'d [u8],
+    pos: usize,
+}
+
+impl<'d> Cursor<'d> {
+
+    fn take(&mut self, len: usize) -> io::Result<&'d [u8]> {
+        let end = self.pos.checked_add(len).filter(|end| *end <= self.data.len())
+            .ok_or_else(|| invalid("archive is truncated"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,194 @@
+use super::serde::{Deserialize, Serialize};
+use super::cards::{CardLibrary, DeckError};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+
+const STARTER_DECK: &str = "Starter";
+
+
+#[derive(Debug)]
+pub enum ProfileError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, super::toml::de::Error),
+    Serialize(PathBuf, super::toml::ser::Error),
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            ProfileError::Parse(path, err) => write!(f, "{}: {}", path.display(), err),
+            ProfileError::Serialize(path, err) => write!(f, "{}: {}", path.display(), err),
+        }
+    }
+}
+
+
+// What the player keeps between runs: the cards unlocked so far and named decks
+// built from them. Cards are named by their key in the cards file, and the file
+// is plain TOML so it can be edited by hand as well.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub unlocked: BTreeSet<String>,
+    // The preset battles start with.
+    pub active_deck: String,
+    pub decks: BTreeMap<String, Vec<String>>,
+}
+
+impl Profile {
+
+    // A new profile owns the cards of the default deck and has it as its only preset.
+    pub fn new(library: &CardLibrary) -> Profile {
+        let mut decks = BTreeMap::new();
+        decks.insert(STARTER_DECK.to_string(), library.deck.clone());
+        Profile { unlocked: library.starter_cards(), active_deck: STARTER_DECK.to_string(), decks }
+    }
+
+    // A missing file is not an error, just a player without a profile yet.
+    pub fn load(path: &Path, library: &CardLibrary) -> Result<Profile, ProfileError> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Profile::new(library)),
+            Err(err) => return Err(ProfileError::Io(path.to_path_buf(), err)),
+        };
+        let mut profile: Profile = super::toml::from_str(&text).map_err(|e| ProfileError::Parse(path.to_path_buf(), e))?;
+        profile.reconcile(library);
+        Ok(profile)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ProfileError> {
+        let text = super::toml::to_string(self).map_err(|e| ProfileError::Serialize(path.to_path_buf(), e))?;
+        fs::write(path, text).map_err(|e| ProfileError::Io(path.to_path_buf(), e))
+    }
+
+    // Keeps the profile usable when the cards file changed under it: the starter
+    // cards stay unlocked, cards that no longer exist are dropped and there is
+    // always an active preset.
+    pub fn reconcile(&mut self, library: &CardLibrary) {
+        self.unlocked.extend(library.starter_cards());
+        self.unlocked.retain(|name| library.get(name).is_some());
+        for deck in self.decks.values_mut() {
+            deck.retain(|name| library.get(name).is_some());
+        }
+        if self.decks.is_empty() {
+            self.decks.insert(STARTER_DECK.to_string(), library.deck.clone());
+        }
+        if !self.decks.contains_key(&self.active_deck) {
+            self.active_deck = self.decks.keys().next().cloned().unwrap_or_default();
+        }
+    }
+
+    pub fn deck(&self) -> &[String] {
+        self.decks.get(&self.active_deck).map_or(&[], Vec::as_slice)
+    }
+
+    fn deck_mut(&mut self) -> &mut Vec<String> {
+        self.decks.entry(self.active_deck.clone()).or_default()
+    }
+
+    pub fn copies(&self, name: &str) -> usize {
+        self.deck().iter().filter(|card| *card == name).count()
+    }
+
+    pub fn check_deck(&self, library: &CardLibrary) -> Result<(), DeckError> {
+        library.check_deck(self.deck())?;
+        match self.deck().iter().find(|name| !self.unlocked.contains(*name)) {
+            Some(name) => Err(DeckError::Locked(name.clone())),
+            None => Ok(()),
+        }
+    }
+
+    // The active preset, or the default deck while the preset breaks the rules,
+    // e.g. after the cards file was edited.
+    pub fn battle_deck(&self, library: &CardLibrary) -> Vec<String> {
+        match self.check_deck(library) {
+            Ok(()) => self.deck().to_vec(),
+            Err(_) => library.deck.clone(),
+        }
+    }
+
+    pub fn add_card(&mut self, name: &str, library: &CardLibrary) -> Result<(), DeckError> {
+        let rules = library.rules;
+        if library.get(name).is_none() {
+            return Err(DeckError::Unknown(name.to_string()));
+        }
+        if !self.unlocked.contains(name) {
+            return Err(DeckError::Locked(name.to_string()));
+        }
+        if self.deck().len() >= rules.max_size {
+            return Err(DeckError::TooLarge { max: rules.max_size });
+        }
+        if self.copies(name) >= rules.max_copies {
+            return Err(DeckError::TooManyCopies { card: name.to_string(), max: rules.max_copies });
+        }
+        let deck = self.deck_mut();
+        deck.push(name.to_string());
+        deck.sort();
+        Ok(())
+    }
+
+    pub fn remove_card(&mut self, name: &str) -> bool {
+        let deck = self.deck_mut();
+        match deck.iter().position(|card| card == name) {
+            Some(index) => {
+                deck.remove(index);
+                true
+            },
+            None => false,
+        }
+    }
+
+    // Makes the next or previous preset in name order the active one.
+    pub fn cycle_deck(&mut self, forward: bool) {
+        let names: Vec<&String> = self.decks.keys().collect();
+        if let Some(index) = names.iter().position(|name| **name == self.active_deck) {
+            let next = if forward { (index + 1) % names.len() } else { (index + names.len() - 1) % names.len() };
+            self.active_deck = names[next].clone();
+        }
+    }
+
+    // Copies the active preset under a fresh name and switches to the copy.
+    pub fn new_deck(&mut self) {
+        let name = (1..).map(|n| format!("Deck {}", n)).find(|name| !self.decks.contains_key(name))
+            .expect("Ran out of deck names");
+        let deck = self.deck().to_vec();
+        self.decks.insert(name.clone(), deck);
+        self.active_deck = name;
+    }
+
+    // Renames the active preset. Names are trimmed, and an empty one or one another
+    // preset already has is refused.
+    pub fn rename_deck(&mut self, name: &str) -> bool {
+        let name = name.trim();
+        if name.is_empty() || (name != self.active_deck && self.decks.contains_key(name)) {
+            return false;
+        }
+        if let Some(deck) = self.decks.remove(&self.active_deck) {
+            self.decks.insert(name.to_string(), deck);
+        }
+        self.active_deck = name.to_string();
+        true
+    }
+
+    // The last preset cannot be deleted.
+    pub fn delete_deck(&mut self) -> bool {
+        if self.decks.len() <= 1 {
+            return false;
+        }
+        self.decks.remove(&self.active_deck);
+        self.active_deck = self.decks.keys().next().cloned().unwrap_or_default();
+        true
+    }
+
+    // Unlocks the first locked card in name order and returns it.
+    pub fn unlock_next(&mut self, library: &CardLibrary) -> Option<String> {
+        let name = library.names().find(|name| !self.unlocked.contains(*name))?.to_string();
+        self.unlocked.insert(name.clone());
+        Some(name)
+    }
+}
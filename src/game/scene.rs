@@ -0,0 +1,121 @@
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Scene {
+    MainMenu,
+    DeckBuilder,
+    Playing,
+    Paused,
+    GameOver,
+    Victory,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MenuAction {
+    Start,
+    Decks,
+    Resume,
+    Restart,
+    QuitToMenu,
+    Quit,
+}
+
+impl Scene {
+
+    // Overlays are drawn on top of whatever scene is below them.
+    pub fn is_overlay(self) -> bool {
+        match self {
+            Scene::Paused | Scene::GameOver | Scene::Victory => true,
+            Scene::MainMenu | Scene::DeckBuilder | Scene::Playing => false,
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Scene::MainMenu => "CASTLE",
+            Scene::DeckBuilder => "DECKS",
+            Scene::Playing => "",
+            Scene::Paused => "PAUSED",
+            Scene::GameOver => "GAME OVER!",
+            Scene::Victory => "VICTORY!",
+        }
+    }
+
+    pub fn menu(self) -> &'static [(&'static str, MenuAction)] {
+        match self {
+            Scene::MainMenu => &[("Start", MenuAction::Start), ("Decks", MenuAction::Decks),
+                                 ("Quit", MenuAction::Quit)],
+            // The deck builder lays out its own widgets.
+            Scene::DeckBuilder | Scene::Playing => &[],
+            Scene::Paused => &[("Resume", MenuAction::Resume), ("Restart", MenuAction::Restart),
+                               ("Quit to Menu", MenuAction::QuitToMenu)],
+            Scene::GameOver | Scene::Victory => &[("Restart", MenuAction::Restart),
+                                                  ("Quit to Menu", MenuAction::QuitToMenu)],
+        }
+    }
+}
+
+
+pub struct SceneStack {
+    scenes: Vec<Scene>,
+    selected: usize,
+}
+
+impl SceneStack {
+
+    pub fn new(root: Scene) -> SceneStack {
+        SceneStack { scenes: vec![root], selected: 0 }
+    }
+
+    pub fn top(&self) -> Scene {
+        *self.scenes.last().expect("Scene stack is empty")
+    }
+
+    pub fn push(&mut self, scene: Scene) {
+        self.scenes.push(scene);
+        self.selected = 0;
+    }
+
+    pub fn pop(&mut self) {
+        if self.scenes.len() > 1 {
+            self.scenes.pop();
+        }
+        self.selected = 0;
+    }
+
+    pub fn reset(&mut self, root: Scene) {
+        self.scenes.clear();
+        self.scenes.push(root);
+        self.selected = 0;
+    }
+
+    // Scenes that need drawing this frame, bottom first.
+    pub fn visible(&self) -> &[Scene] {
+        let start = self.scenes.iter().rposition(|s| !s.is_overlay()).unwrap_or(0);
+        &self.scenes[start..]
+    }
+
+    pub fn selected(&self) -> usize { self.selected }
+
+    pub fn select(&mut self, index: usize) {
+        if index < self.top().menu().len() {
+            self.selected = index;
+        }
+    }
+
+    pub fn select_next(&mut self) {
+        let len = self.top().menu().len();
+        if len > 0 {
+            self.selected = (self.selected + 1) % len;
+        }
+    }
+
+    pub fn select_prev(&mut self) {
+        let len = self.top().menu().len();
+        if len > 0 {
+            self.selected = (self.selected + len - 1) % len;
+        }
+    }
+
+    pub fn selected_action(&self) -> Option<MenuAction> {
+        self.top().menu().get(self.selected).map(|(_, action)| *action)
+    }
+}
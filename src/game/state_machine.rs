@@ -0,0 +1,129 @@
+use super::rand::rngs::StdRng;
+use super::timer::Timer;
+use crate::settings::Settings;
+
+
+pub struct StateContext<'r> {
+    pub dt: f32,
+    pub rng: &'r mut StdRng,
+    pub settings: &'r Settings,
+}
+
+pub type Guard<C> = fn(&C) -> bool;
+pub type Hook<C> = fn(&mut C, &mut StateContext<'_>);
+
+
+struct Transition<S, C> {
+    from: S,
+    to: S,
+    guard: Guard<C>,
+}
+
+struct StateHooks<S, C> {
+    state: S,
+    on_enter: Option<Hook<C>>,
+    on_exit: Option<Hook<C>>,
+    on_update: Option<Hook<C>>,
+}
+
+
+// Transitions are checked in the order they were declared, and at most one
+// fires per update; the hooks run with full mutable access to the owner.
+pub struct StateMachine<S, C> {
+    current: S,
+    timer: Timer,
+    transitions: Vec<Transition<S, C>>,
+    hooks: Vec<StateHooks<S, C>>,
+}
+
+pub trait StateMachineOwner<S>: Sized {
+    fn state_machine(&self) -> &StateMachine<S, Self>;
+    fn state_machine_mut(&mut self) -> &mut StateMachine<S, Self>;
+}
+
+impl<S: Copy + Eq, C> StateMachine<S, C> {
+
+    pub fn new(initial: S) -> StateMachine<S, C> {
+        StateMachine {
+            current: initial,
+            timer: Timer::new(),
+            transitions: Vec::new(),
+            hooks: Vec::new(),
+        }
+    }
+
+    pub fn transition(mut self, from: S, to: S, guard: Guard<C>) -> Self {
+        self.transitions.push(Transition { from, to, guard });
+        self
+    }
+
+    pub fn on_enter(mut self, state: S, hook: Hook<C>) -> Self {
+        self.hooks_mut(state).on_enter = Some(hook);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn on_exit(mut self, state: S, hook: Hook<C>) -> Self {
+        self.hooks_mut(state).on_exit = Some(hook);
+        self
+    }
+
+    pub fn on_update(mut self, state: S, hook: Hook<C>) -> Self {
+        self.hooks_mut(state).on_update = Some(hook);
+        self
+    }
+
+    pub fn current(&self) -> S { self.current }
+
+    pub fn time_in_state(&self) -> f32 { self.timer.elapsed_seconds() }
+
+    fn hooks_mut(&mut self, state: S) -> &mut StateHooks<S, C> {
+        if let Some(index) = self.hooks.iter().position(|h| h.state == state) {
+            return &mut self.hooks[index];
+        }
+        self.hooks.push(StateHooks { state, on_enter: None, on_exit: None, on_update: None });
+        self.hooks.last_mut().unwrap()
+    }
+
+    fn hooks(&self, state: S) -> Option<&StateHooks<S, C>> {
+        self.hooks.iter().find(|h| h.state == state)
+    }
+}
+
+
+pub fn update<S, C>(owner: &mut C, ctx: &mut StateContext<'_>)
+    where S: Copy + Eq, C: StateMachineOwner<S> {
+    owner.state_machine_mut().timer.tick(ctx.dt);
+
+    let next = {
+        let owner_ref: &C = owner;
+        let machine = owner_ref.state_machine();
+        machine.transitions.iter()
+            .find(|t| t.from == machine.current && (t.guard)(owner_ref))
+            .map(|t| t.to)
+    };
+    if let Some(next) = next {
+        change_state(owner, next, ctx);
+    }
+
+    let machine = owner.state_machine();
+    if let Some(on_update) = machine.hooks(machine.current).and_then(|h| h.on_update) {
+        on_update(owner, ctx);
+    }
+}
+
+pub fn change_state<S, C>(owner: &mut C, next: S, ctx: &mut StateContext<'_>)
+    where S: Copy + Eq, C: StateMachineOwner<S> {
+    let machine = owner.state_machine();
+    if let Some(on_exit) = machine.hooks(machine.current).and_then(|h| h.on_exit) {
+        on_exit(owner, ctx);
+    }
+
+    let machine = owner.state_machine_mut();
+    machine.current = next;
+    machine.timer.restart();
+
+    if let Some(on_enter) = owner.state_machine().hooks(next).and_then(|h| h.on_enter) {
+        on_enter(owner, ctx);
+    }
+}
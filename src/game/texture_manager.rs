@@ -1,19 +1,49 @@
+use super::graphics::{IntRect, Texture};
+use super::sfml::system::SfBox;
+use super::assets::AssetLocator;
 use std::collections::HashMap;
 
+
+const IMAGE_EXTENSIONS: [&str; 4] = ["png", "jpg", "bmp", "tga"];
+
+
+// Every texture is loaded once up front and lives as long as the manager, so
+// entities can hold plain `&'a Texture` references for the whole game. Textures
+// are named by their path under the textures directory, with `/` separators:
+// "units/militia.png".
 pub struct TextureManager {
-    dict: HashMap<&str, &str>,
+    dict: HashMap<String, SfBox<Texture>>,
 }
 
 impl TextureManager {
 
+    // A manager without textures; everything falls back to coloured rectangles.
     pub fn new() -> TextureManager {
-        let dict: HashMap<&str, &str> = HashMap::new();
-        TextureManager {
-            dict,
-        }
+        TextureManager { dict: HashMap::new() }
     }
 
-    pub fn load_target(&mut self) {
+    pub fn load(assets: &AssetLocator, dir: &str) -> TextureManager {
+        let mut manager = TextureManager::new();
+        let prefix = format!("{}/", dir.trim_end_matches('/'));
+        for path in assets.list(dir) {
+            let is_image = path.rsplit('.').next()
+                .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()));
+            if !is_image {
+                continue;
+            }
+            let texture = assets.read(&path).ok()
+                .and_then(|data| Texture::from_memory(&data, &IntRect::new(0, 0, 0, 0)));
+            match texture {
+                Some(texture) => {
+                    manager.dict.insert(path[prefix.len()..].to_string(), texture);
+                },
+                None => eprintln!("Error loading texture {}", path),
+            }
+        }
+        manager
+    }
 
+    pub fn get(&self, name: &str) -> Option<&Texture> {
+        self.dict.get(name).map(|texture| &**texture)
     }
 }
@@ -0,0 +1,61 @@
+// Advanced by the simulation instead of reading wall time, so the same
+// sequence of ticks always yields the same elapsed values.
+#[derive(Clone, Debug, Default)]
+pub struct Timer {
+    elapsed: f32,
+}
+
+impl Timer {
+
+    pub fn new() -> Timer {
+        Timer { elapsed: 0.0 }
+    }
+
+    pub fn tick(&mut self, dt: f32) {
+        self.elapsed += dt;
+    }
+
+    pub fn elapsed_seconds(&self) -> f32 {
+        self.elapsed
+    }
+
+    pub fn restart(&mut self) -> f32 {
+        let elapsed = self.elapsed;
+        self.elapsed = 0.0;
+        elapsed
+    }
+}
+
+
+// Counts down in game time; `tick` is only called while the simulation runs,
+// so pausing or scaling the game speed pauses or scales the cooldown too.
+#[derive(Clone, Debug)]
+pub struct Cooldown {
+    duration: f32,
+    remaining: f32,
+}
+
+impl Cooldown {
+
+    pub fn new(duration: f32) -> Cooldown {
+        Cooldown { duration, remaining: duration }
+    }
+
+    pub fn tick(&mut self, dt: f32) {
+        self.remaining = (self.remaining - dt).max(0.0);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.remaining <= 0.0
+    }
+
+    // Keeps whatever is left of the current wait, but never more than the new duration.
+    pub fn set_duration(&mut self, duration: f32) {
+        self.duration = duration;
+        self.remaining = self.remaining.min(duration);
+    }
+
+    pub fn trigger(&mut self) {
+        self.remaining = self.duration;
+    }
+}
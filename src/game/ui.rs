@@ -1,12 +1,686 @@
 use super::graphics::*;
 use super::system::Vector2f;
-use super::super::settings::{HUMANS_MAX_HP, BUILDING_BASE_MAX_HP};
+use super::cards::Cards;
+use std::fmt::Write;
 
 
-struct UIString;
+// A line of text that keeps its alignment when the string changes, so callers can
+// hold one and update it instead of building a new `Text` for every frame.
+pub struct UIString<'a> {
+    text: Text<'a>,
+    string: String,
+    centered: bool,
+}
+
+impl<'a> UIString<'a> {
+
+    pub fn new(string: &str, font: &'a Font, size: u32, color: Color) -> UIString<'a> {
+        let mut text = Text::new(string, font, size);
+        text.set_fill_color(color);
+        UIString { text, string: string.to_string(), centered: false }
+    }
+
+    // Positions refer to the middle of the text from now on.
+    pub fn centered(mut self) -> UIString<'a> {
+        self.centered = true;
+        self.realign();
+        self
+    }
+
+    // Returns whether the text changed.
+    pub fn set_string(&mut self, string: &str) -> bool {
+        if self.string == string {
+            return false;
+        }
+        self.string.clear();
+        self.string.push_str(string);
+        self.text.set_string(string);
+        self.realign();
+        true
+    }
+
+    pub fn set_color(&mut self, color: Color) {
+        self.text.set_fill_color(color);
+    }
+
+    pub fn set_position(&mut self, pos: Vector2f) {
+        self.text.set_position(pos);
+    }
+
+    fn realign(&mut self) {
+        if self.centered {
+            let bounds = self.text.local_bounds();
+            self.text.set_origin(Vector2f::new(bounds.left + bounds.width / 2.0, bounds.top + bounds.height / 2.0));
+        }
+    }
+
+    // Width of the text and the font's line height, so labels line up however
+    // tall their letters are.
+    pub fn size(&self) -> Vector2f {
+        let bounds = self.text.local_bounds();
+        let line_height = self.text.font().map_or(0, |font| font.line_spacing(self.text.character_size()));
+        Vector2f::new(bounds.left + bounds.width, line_height as f32)
+    }
+
+    pub fn draw(&self, win: &mut RenderWindow) {
+        win.draw(&self.text);
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Direction {
+    Horizontal,
+    Vertical,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct WidgetId(usize);
+
+
+// Where children sit across the layout direction: top, middle or bottom of a
+// horizontal panel, left, middle or right of a vertical one.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Align {
+    Start,
+    Center,
+    End,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PanelStyle {
+    pub padding: f32,
+    pub spacing: f32,
+    pub background: Color,
+    pub align: Align,
+}
+
+impl Default for PanelStyle {
+    fn default() -> PanelStyle {
+        PanelStyle { padding: 0.0, spacing: 8.0, background: Color::TRANSPARENT, align: Align::Start }
+    }
+}
+
+
+struct Panel<'a> {
+    background: RectangleShape<'a>,
+    direction: Direction,
+    style: PanelStyle,
+    min_size: Vector2f,
+    children: Vec<WidgetId>,
+}
+
+struct Button<'a, A> {
+    frame: RectangleShape<'a>,
+    label: UIString<'a>,
+    action: A,
+    hovered: bool,
+    highlighted: bool,
+}
+
+impl<A> Button<'_, A> {
+
+    fn restyle(&mut self) {
+        let color = if self.hovered || self.highlighted { Color::YELLOW } else { Color::WHITE };
+        self.frame.set_outline_color(color);
+        self.label.set_color(color);
+    }
+}
+
+struct ProgressBar<'a> {
+    frame: RectangleShape<'a>,
+    fill: RectangleShape<'a>,
+}
+
+enum Kind<'a, A> {
+    Label(UIString<'a>),
+    Button(Button<'a, A>),
+    Panel(Panel<'a>),
+    ProgressBar(ProgressBar<'a>),
+    Image(RectangleShape<'a>),
+}
+
+struct Node<'a, A> {
+    kind: Kind<'a, A>,
+    visible: bool,
+    // Only used on roots: `pivot` is the point of the widget, as a fraction of its
+    // size, that sits on `anchor`.
+    anchor: Vector2f,
+    pivot: Vector2f,
+    // Where the last layout put the widget.
+    bounds: FloatRect,
+}
+
+
+// Retained widget tree. Widgets are created once, addressed by the `WidgetId` they
+// were created with and updated in place; positions come from the panels they sit
+// in, or from `set_anchor` for widgets without a parent. Clicking a button yields
+// its action.
+pub struct Ui<'a, A> {
+    font: &'a Font,
+    nodes: Vec<Node<'a, A>>,
+    roots: Vec<WidgetId>,
+    dirty: bool,
+}
+
+impl<'a, A: Copy> Ui<'a, A> {
+
+    pub fn new(font: &'a Font) -> Ui<'a, A> {
+        Ui { font, nodes: Vec::new(), roots: Vec::new(), dirty: false }
+    }
 
+    fn add(&mut self, parent: Option<WidgetId>, kind: Kind<'a, A>) -> WidgetId {
+        let id = WidgetId(self.nodes.len());
+        let origin = Vector2f::new(0.0, 0.0);
+        self.nodes.push(Node { kind, visible: true, anchor: origin, pivot: origin, bounds: FloatRect::default() });
+        match parent {
+            Some(parent) => match &mut self.nodes[parent.0].kind {
+                Kind::Panel(panel) => panel.children.push(id),
+                _ => panic!("Widget parent is not a panel"),
+            },
+            None => self.roots.push(id),
+        }
+        self.dirty = true;
+        id
+    }
+
+    pub fn panel(&mut self, parent: Option<WidgetId>, direction: Direction, style: PanelStyle) -> WidgetId {
+        let mut background = RectangleShape::new();
+        background.set_fill_color(style.background);
+        self.add(parent, Kind::Panel(Panel { background, direction, style, min_size: Vector2f::new(0.0, 0.0), children: Vec::new() }))
+    }
+
+    pub fn label(&mut self, parent: Option<WidgetId>, text: &str, size: u32, color: Color) -> WidgetId {
+        let label = UIString::new(text, self.font, size, color);
+        self.add(parent, Kind::Label(label))
+    }
 
-struct UICardsInfo;
+    pub fn button(&mut self, parent: Option<WidgetId>, text: &str, size: Vector2f, action: A) -> WidgetId {
+        let mut frame = RectangleShape::with_size(size);
+        frame.set_fill_color(Color::rgba(30, 30, 30, 200));
+        frame.set_outline_thickness(2.0);
+        let mut button = Button {
+            frame,
+            label: UIString::new(text, self.font, (size.y * 0.5) as u32, Color::WHITE).centered(),
+            action,
+            hovered: false,
+            highlighted: false,
+        };
+        button.restyle();
+        self.add(parent, Kind::Button(button))
+    }
+
+    pub fn progress_bar(&mut self, parent: Option<WidgetId>, size: Vector2f, color: Color) -> WidgetId {
+        let mut frame = RectangleShape::with_size(size);
+        frame.set_fill_color(Color::TRANSPARENT);
+        frame.set_outline_thickness(2.0);
+        frame.set_outline_color(Color::WHITE);
+        let mut fill = RectangleShape::with_size(size);
+        fill.set_fill_color(color);
+        self.add(parent, Kind::ProgressBar(ProgressBar { frame, fill }))
+    }
+
+    // Without a texture the image is a flat `fallback` rectangle.
+    pub fn image(&mut self, parent: Option<WidgetId>, texture: Option<&'a Texture>, size: Vector2f,
+                 fallback: Color) -> WidgetId {
+        let mut rect = RectangleShape::with_size(size);
+        match texture {
+            Some(texture) => rect.set_texture(texture, true),
+            None => rect.set_fill_color(fallback),
+        }
+        self.add(parent, Kind::Image(rect))
+    }
+
+    pub fn set_anchor(&mut self, id: WidgetId, anchor: Vector2f, pivot: Vector2f) {
+        let node = &mut self.nodes[id.0];
+        node.anchor = anchor;
+        node.pivot = pivot;
+        self.dirty = true;
+    }
+
+    pub fn set_text(&mut self, id: WidgetId, text: &str) {
+        let changed = match &mut self.nodes[id.0].kind {
+            Kind::Label(label) => label.set_string(text),
+            Kind::Button(button) => button.label.set_string(text),
+            _ => false,
+        };
+        if changed {
+            self.dirty = true;
+        }
+    }
+
+    pub fn set_color(&mut self, id: WidgetId, color: Color) {
+        match &mut self.nodes[id.0].kind {
+            Kind::Label(label) => label.set_color(color),
+            Kind::ProgressBar(bar) => bar.fill.set_fill_color(color),
+            Kind::Image(rect) => rect.set_fill_color(color),
+            Kind::Panel(panel) => panel.background.set_fill_color(color),
+            Kind::Button(_) => {},
+        }
+    }
+
+    pub fn set_progress(&mut self, id: WidgetId, ratio: f32) {
+        if let Kind::ProgressBar(bar) = &mut self.nodes[id.0].kind {
+            let size = bar.frame.size();
+            bar.fill.set_size(Vector2f::new(size.x * ratio.clamp(0.0, 1.0), size.y));
+        }
+    }
+
+    // A panel never shrinks below `size`, however little its children need.
+    pub fn set_min_size(&mut self, id: WidgetId, size: Vector2f) {
+        if let Kind::Panel(panel) = &mut self.nodes[id.0].kind {
+            if panel.min_size != size {
+                panel.min_size = size;
+                self.dirty = true;
+            }
+        }
+    }
+
+    // Keyboard selection, shown the same way as hovering with the mouse.
+    pub fn set_highlighted(&mut self, id: WidgetId, highlighted: bool) {
+        if let Kind::Button(button) = &mut self.nodes[id.0].kind {
+            button.highlighted = highlighted;
+            button.restyle();
+        }
+    }
+
+    // Hidden widgets take no space in their panel and ignore the mouse.
+    pub fn set_visible(&mut self, id: WidgetId, visible: bool) {
+        if self.nodes[id.0].visible != visible {
+            self.nodes[id.0].visible = visible;
+            self.dirty = true;
+        }
+    }
+
+    fn size(&self, id: WidgetId) -> Vector2f {
+        let node = &self.nodes[id.0];
+        if !node.visible {
+            return Vector2f::new(0.0, 0.0);
+        }
+        match &node.kind {
+            Kind::Label(label) => label.size(),
+            Kind::Button(button) => button.frame.size(),
+            Kind::ProgressBar(bar) => bar.frame.size(),
+            Kind::Image(rect) => rect.size(),
+            Kind::Panel(panel) => {
+                let mut size = Vector2f::new(0.0, 0.0);
+                let mut shown = 0;
+                for child in panel.children.iter().filter(|child| self.nodes[child.0].visible) {
+                    let child_size = self.size(*child);
+                    match panel.direction {
+                        Direction::Horizontal => {
+                            size.x += child_size.x;
+                            size.y = size.y.max(child_size.y);
+                        },
+                        Direction::Vertical => {
+                            size.x = size.x.max(child_size.x);
+                            size.y += child_size.y;
+                        },
+                    }
+                    shown += 1;
+                }
+                let gaps = panel.style.spacing * (shown.max(1) - 1) as f32;
+                match panel.direction {
+                    Direction::Horizontal => size.x += gaps,
+                    Direction::Vertical => size.y += gaps,
+                }
+                size += Vector2f::new(panel.style.padding * 2.0, panel.style.padding * 2.0);
+                Vector2f::new(size.x.max(panel.min_size.x), size.y.max(panel.min_size.y))
+            },
+        }
+    }
+
+    fn layout(&mut self) {
+        for index in 0..self.roots.len() {
+            let root = self.roots[index];
+            let size = self.size(root);
+            let node = &self.nodes[root.0];
+            let position = node.anchor - Vector2f::new(size.x * node.pivot.x, size.y * node.pivot.y);
+            self.place(root, position, size);
+        }
+        self.dirty = false;
+    }
+
+    fn place(&mut self, id: WidgetId, position: Vector2f, size: Vector2f) {
+        self.nodes[id.0].bounds = FloatRect::new(position.x, position.y, size.x, size.y);
+        let (direction, style, count) = match &mut self.nodes[id.0].kind {
+            Kind::Label(label) => {
+                label.set_position(position);
+                return;
+            },
+            Kind::Button(button) => {
+                button.frame.set_position(position);
+                button.label.set_position(position + size / 2.0);
+                return;
+            },
+            Kind::ProgressBar(bar) => {
+                bar.frame.set_position(position);
+                bar.fill.set_position(position);
+                return;
+            },
+            Kind::Image(rect) => {
+                rect.set_position(position);
+                return;
+            },
+            Kind::Panel(panel) => {
+                panel.background.set_position(position);
+                panel.background.set_size(size);
+                (panel.direction, panel.style, panel.children.len())
+            },
+        };
+
+        let mut cursor = position + Vector2f::new(style.padding, style.padding);
+        for index in 0..count {
+            let child = match &self.nodes[id.0].kind {
+                Kind::Panel(panel) => panel.children[index],
+                _ => unreachable!(),
+            };
+            if !self.nodes[child.0].visible {
+                continue;
+            }
+            let child_size = self.size(child);
+            let mut child_pos = cursor;
+            let share = match style.align {
+                Align::Start => 0.0,
+                Align::Center => 0.5,
+                Align::End => 1.0,
+            };
+            match direction {
+                Direction::Horizontal => {
+                    child_pos.y += (size.y - style.padding * 2.0 - child_size.y) * share;
+                },
+                Direction::Vertical => {
+                    child_pos.x += (size.x - style.padding * 2.0 - child_size.x) * share;
+                },
+            }
+            self.place(child, child_pos, child_size);
+            match direction {
+                Direction::Horizontal => cursor.x += child_size.x + style.spacing,
+                Direction::Vertical => cursor.y += child_size.y + style.spacing,
+            }
+        }
+    }
+
+    // Whether the widget and every panel it sits in are shown.
+    fn is_shown(&self, id: WidgetId) -> bool {
+        self.roots.iter().any(|root| self.shown_below(*root, id))
+    }
+
+    fn shown_below(&self, from: WidgetId, id: WidgetId) -> bool {
+        if !self.nodes[from.0].visible {
+            return false;
+        }
+        if from == id {
+            return true;
+        }
+        match &self.nodes[from.0].kind {
+            Kind::Panel(panel) => panel.children.iter().any(|child| self.shown_below(*child, id)),
+            _ => false,
+        }
+    }
+
+    fn button_at(&self, pos: Vector2f) -> Option<WidgetId> {
+        (0..self.nodes.len()).rev().map(WidgetId).find(|id| match &self.nodes[id.0].kind {
+            Kind::Button(button) => button.frame.global_bounds().contains(pos) && self.is_shown(*id),
+            _ => false,
+        })
+    }
+
+    // Whether `pos` is over the widget, which has to be shown.
+    pub fn contains(&mut self, id: WidgetId, pos: Vector2f) -> bool {
+        if self.dirty {
+            self.layout();
+        }
+        self.is_shown(id) && self.nodes[id.0].bounds.contains(pos)
+    }
+
+    // Returns the button under the mouse, if any.
+    pub fn mouse_moved(&mut self, pos: Vector2f) -> Option<WidgetId> {
+        if self.dirty {
+            self.layout();
+        }
+        let hovered = self.button_at(pos);
+        for (index, node) in self.nodes.iter_mut().enumerate() {
+            if let Kind::Button(button) = &mut node.kind {
+                let is_hovered = hovered == Some(WidgetId(index));
+                if button.hovered != is_hovered {
+                    button.hovered = is_hovered;
+                    button.restyle();
+                }
+            }
+        }
+        hovered
+    }
+
+    pub fn click(&mut self, pos: Vector2f) -> Option<A> {
+        if self.dirty {
+            self.layout();
+        }
+        self.button_at(pos).and_then(|id| match &self.nodes[id.0].kind {
+            Kind::Button(button) => Some(button.action),
+            _ => None,
+        })
+    }
+
+    pub fn draw(&mut self, win: &mut RenderWindow) {
+        if self.dirty {
+            self.layout();
+        }
+        for index in 0..self.roots.len() {
+            self.draw_widget(self.roots[index], win);
+        }
+    }
+
+    fn draw_widget(&self, id: WidgetId, win: &mut RenderWindow) {
+        let node = &self.nodes[id.0];
+        if !node.visible {
+            return;
+        }
+        match &node.kind {
+            Kind::Label(label) => label.draw(win),
+            Kind::Button(button) => {
+                win.draw(&button.frame);
+                button.label.draw(win);
+            },
+            Kind::ProgressBar(bar) => {
+                win.draw(&bar.fill);
+                win.draw(&bar.frame);
+            },
+            Kind::Image(rect) => win.draw(rect),
+            Kind::Panel(panel) => {
+                win.draw(&panel.background);
+                for child in &panel.children {
+                    self.draw_widget(*child, win);
+                }
+            },
+        }
+    }
+}
+
+
+const CARD_SIZE: Vector2f = Vector2f { x: 110.0, y: 150.0 };
+const CARD_HOVER_SIZE: Vector2f = Vector2f { x: 132.0, y: 180.0 };
+const CARD_COLOR: Color = Color { r: 70, g: 60, b: 45, a: 235 };
+const CARD_UNAFFORDABLE_COLOR: Color = Color { r: 50, g: 50, b: 50, a: 200 };
+
+
+struct CardSlot {
+    face: WidgetId,
+    name: WidgetId,
+    cost: WidgetId,
+    cost_value: f32,
+}
+
+
+// The hand along the bottom of the screen. A card grows while the mouse is over
+// it and is greyed out while it costs more gold than there is. Pressing on an
+// affordable card picks it up; letting go over the battlefield plays it there,
+// while letting go over the hand, right clicking or pressing Escape puts it back.
+pub struct UICardsInfo<'a> {
+    font: &'a Font,
+    window_size: Vector2f,
+    ui: Ui<'a, ()>,
+    row: Option<WidgetId>,
+    slots: Vec<CardSlot>,
+    piles: Option<WidgetId>,
+    // The hand the widgets were built for.
+    shown: Vec<String>,
+    hovered: Option<usize>,
+    dragging: Option<usize>,
+    pointer: Vector2f,
+    preview: RectangleShape<'a>,
+    buffer: String,
+}
+
+impl<'a> UICardsInfo<'a> {
+
+    pub fn new(font: &'a Font, window_size: Vector2f) -> UICardsInfo<'a> {
+        let mut preview = RectangleShape::new();
+        preview.set_outline_thickness(2.0);
+        UICardsInfo {
+            font,
+            window_size,
+            ui: Ui::new(font),
+            row: None,
+            slots: Vec::new(),
+            piles: None,
+            shown: Vec::new(),
+            hovered: None,
+            dragging: None,
+            pointer: Vector2f::new(0.0, 0.0),
+            preview,
+            buffer: String::new(),
+        }
+    }
+
+    fn rebuild(&mut self, cards: &Cards) {
+        let mut ui = Ui::new(self.font);
+        let style = PanelStyle { padding: 8.0, spacing: 10.0, align: Align::End, ..PanelStyle::default() };
+        let row = ui.panel(None, Direction::Horizontal, style);
+        ui.set_anchor(row, Vector2f::new(self.window_size.x / 2.0, self.window_size.y), Vector2f::new(0.5, 1.0));
+
+        self.slots.clear();
+        for index in 0..cards.hand().len() {
+            let card = match cards.hand_card(index) {
+                Some(card) => card,
+                None => continue,
+            };
+            let face_style = PanelStyle { padding: 8.0, spacing: 6.0, background: CARD_COLOR, align: Align::Center };
+            let face = ui.panel(Some(row), Direction::Vertical, face_style);
+            ui.set_min_size(face, CARD_SIZE);
+            let name = ui.label(Some(face), &card.name, 16, Color::WHITE);
+            ui.label(Some(face), card.effect.kind_name(), 13, Color::rgb(200, 200, 200));
+            let cost = ui.label(Some(face), &format!("{:.0} gold", card.cost), 16, Color::YELLOW);
+            self.slots.push(CardSlot { face, name, cost, cost_value: card.cost });
+        }
+        self.piles = Some(ui.label(Some(row), "", 14, Color::WHITE));
+        self.row = Some(row);
+        self.ui = ui;
+        self.hovered = None;
+    }
+
+    // Follows the hand and the gold every frame, rebuilding only when the hand changed.
+    pub fn sync(&mut self, cards: &Cards, gold: f32) {
+        if self.shown.as_slice() != cards.hand() {
+            if let Some(index) = self.dragging {
+                if self.shown.get(index) != cards.hand().get(index) {
+                    self.dragging = None;
+                }
+            }
+            self.shown.clear();
+            self.shown.extend_from_slice(cards.hand());
+            self.rebuild(cards);
+        }
+
+        for (index, slot) in self.slots.iter().enumerate() {
+            let affordable = slot.cost_value <= gold;
+            let grown = self.hovered == Some(index) || self.dragging == Some(index);
+            self.ui.set_min_size(slot.face, if grown { CARD_HOVER_SIZE } else { CARD_SIZE });
+            self.ui.set_color(slot.face, if affordable { CARD_COLOR } else { CARD_UNAFFORDABLE_COLOR });
+            self.ui.set_color(slot.name, if affordable { Color::WHITE } else { Color::rgb(140, 140, 140) });
+            self.ui.set_color(slot.cost, if affordable { Color::YELLOW } else { Color::RED });
+        }
+        if let Some(piles) = self.piles {
+            self.buffer.clear();
+            let _ = write!(self.buffer, "Draw {}\nDiscard {}", cards.draw_pile_len(), cards.discard_pile_len());
+            self.ui.set_text(piles, &self.buffer);
+        }
+    }
+
+    fn card_at(&mut self, pos: Vector2f) -> Option<usize> {
+        let ui = &mut self.ui;
+        self.slots.iter().position(|slot| ui.contains(slot.face, pos))
+    }
+
+    pub fn is_over_hand(&mut self, pos: Vector2f) -> bool {
+        match self.row {
+            Some(row) => self.ui.contains(row, pos),
+            None => false,
+        }
+    }
+
+    pub fn mouse_moved(&mut self, pos: Vector2f) {
+        self.pointer = pos;
+        self.hovered = self.card_at(pos);
+    }
+
+    // Picks up the card under the mouse if it can be paid for.
+    pub fn mouse_pressed(&mut self, pos: Vector2f, gold: f32) {
+        self.pointer = pos;
+        if let Some(index) = self.card_at(pos) {
+            if self.slots[index].cost_value <= gold {
+                self.dragging = Some(index);
+            }
+        }
+    }
+
+    // The card to play if it was let go of over the battlefield.
+    pub fn mouse_released(&mut self, pos: Vector2f) -> Option<usize> {
+        self.pointer = pos;
+        let index = self.dragging.take()?;
+        if self.is_over_hand(pos) {
+            None
+        } else {
+            Some(index)
+        }
+    }
+
+    pub fn cancel(&mut self) {
+        self.dragging = None;
+    }
+
+    pub fn dragging(&self) -> Option<usize> { self.dragging }
+
+    // Where the mouse is, in screen coordinates.
+    pub fn pointer(&self) -> Vector2f { self.pointer }
+
+    // Outlines the ground the dragged card would cover, bottom centre at `pos`;
+    // drawn in world space under the camera's view. With a texture it is a see-through
+    // ghost of what will be placed there.
+    pub fn draw_preview(&mut self, win: &mut RenderWindow, pos: Vector2f, size: [f32; 2], color: Color,
+                        texture: Option<&'a Texture>) {
+        let [width, height] = size;
+        self.preview.set_size(Vector2f::new(width, height));
+        self.preview.set_origin(Vector2f::new(width / 2.0, height));
+        self.preview.set_position(pos);
+        match texture {
+            Some(texture) => {
+                self.preview.set_texture(texture, true);
+                self.preview.set_fill_color(Color::rgba(color.r / 2 + 128, color.g / 2 + 128, color.b / 2 + 128, 150));
+            },
+            None => {
+                self.preview.disable_texture();
+                self.preview.set_fill_color(Color::rgba(color.r, color.g, color.b, 80));
+            },
+        }
+        self.preview.set_outline_color(color);
+        win.draw(&self.preview);
+    }
+
+    pub fn draw(&mut self, win: &mut RenderWindow) {
+        self.ui.draw(win);
+    }
+}
 
 
 pub trait GeoInfo {
@@ -14,22 +688,24 @@ pub trait GeoInfo {
     fn get_size(&self) -> Vector2f;
     fn is_human(&self) -> bool;
     fn geoinfo_get_hp(&self) -> f32;
+    fn geoinfo_get_max_hp(&self) -> f32;
 }
 
 
 pub trait DrawHP: GeoInfo {
     fn draw_hp(&self, win: &mut RenderWindow) {
+        let hp_ratio = (self.geoinfo_get_hp() / self.geoinfo_get_max_hp()).clamp(0.0, 1.0);
         let mut hp_rect = RectangleShape::new();
         let mut hp_bound = RectangleShape::new();
         const HUMAN_HP_BAR_WIDTH: f32 = 60.0;
         const BUILDING_HP_BAR_WIDTH: f32 = 100.0;
         if self.is_human() {
-            hp_rect.set_size(Vector2f::new(HUMAN_HP_BAR_WIDTH * self.geoinfo_get_hp() / HUMANS_MAX_HP, 10.0));
+            hp_rect.set_size(Vector2f::new(HUMAN_HP_BAR_WIDTH * hp_ratio, 10.0));
             hp_bound.set_size(Vector2f::new(HUMAN_HP_BAR_WIDTH, 10.0));
             hp_rect.set_position(Vector2f::new(self.get_position().x - HUMAN_HP_BAR_WIDTH / 2.0, self.get_position().y - self.get_size().y - 25.0));
             hp_bound.set_position(Vector2f::new(self.get_position().x - HUMAN_HP_BAR_WIDTH / 2.0, self.get_position().y - self.get_size().y - 25.0));
         } else {
-            hp_rect.set_size(Vector2f::new(BUILDING_HP_BAR_WIDTH * self.geoinfo_get_hp() / BUILDING_BASE_MAX_HP, 10.0));
+            hp_rect.set_size(Vector2f::new(BUILDING_HP_BAR_WIDTH * hp_ratio, 10.0));
             hp_bound.set_size(Vector2f::new(BUILDING_HP_BAR_WIDTH, 10.0));
             hp_rect.set_position(Vector2f::new(self.get_position().x - BUILDING_HP_BAR_WIDTH / 2.0, self.get_position().y - self.get_size().y - 25.0));
             hp_bound.set_position(Vector2f::new(self.get_position().x - BUILDING_HP_BAR_WIDTH / 2.0, self.get_position().y - self.get_size().y - 25.0));
@@ -0,0 +1,65 @@
+use super::timer::Timer;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+
+const POLL_INTERVAL: f32 = 0.5;
+
+
+// Polls modification times rather than relying on OS notifications; with a
+// handful of small files checked twice a second that is cheap and portable.
+// A file that disappears or shows up counts as a change as well.
+pub struct FileWatcher {
+    files: Vec<PathBuf>,
+    dirs: Vec<PathBuf>,
+    stamps: BTreeMap<PathBuf, Option<SystemTime>>,
+    poll_timer: Timer,
+}
+
+impl FileWatcher {
+
+    // `dirs` are watched for the `*.toml` files directly inside them.
+    pub fn new(files: Vec<PathBuf>, dirs: Vec<PathBuf>) -> FileWatcher {
+        let mut watcher = FileWatcher { files, dirs, stamps: BTreeMap::new(), poll_timer: Timer::new() };
+        watcher.stamps = watcher.scan();
+        watcher
+    }
+
+    // Driven by wall time so edits are picked up while the game is paused.
+    pub fn poll(&mut self, frame_time: f32) -> bool {
+        self.poll_timer.tick(frame_time);
+        if self.poll_timer.elapsed_seconds() < POLL_INTERVAL {
+            return false;
+        }
+        self.poll_timer.restart();
+        let stamps = self.scan();
+        if stamps == self.stamps {
+            return false;
+        }
+        self.stamps = stamps;
+        true
+    }
+
+    fn scan(&self) -> BTreeMap<PathBuf, Option<SystemTime>> {
+        let mut stamps = BTreeMap::new();
+        for file in &self.files {
+            stamps.insert(file.clone(), modified(file));
+        }
+        for dir in &self.dirs {
+            if let Ok(entries) = fs::read_dir(dir) {
+                for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+                    if path.extension().is_some_and(|ext| ext == "toml") {
+                        stamps.insert(path.clone(), modified(&path));
+                    }
+                }
+            }
+        }
+        stamps
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
@@ -0,0 +1,172 @@
+use super::serde::Deserialize;
+use super::archetype::{UnitRegistry, DataError};
+use super::handle::Team;
+use super::timer::Timer;
+use super::EnemyComing;
+use super::assets::AssetLocator;
+use std::collections::VecDeque;
+use std::path::Path;
+
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SpawnGroup {
+    pub archetype: String,
+    pub count: u32,
+    pub side: EnemyComing,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct WaveDefinition {
+    // Seconds between the previous wave being cleared and this one starting.
+    pub delay: f32,
+    // Seconds between two spawns of this wave.
+    pub interval: f32,
+    #[serde(default, rename = "group")]
+    pub groups: Vec<SpawnGroup>,
+}
+
+// A defender already standing when the battle starts, `offset` from the centre
+// of the world where the base is built.
+#[derive(Clone, Debug, Deserialize)]
+pub struct StartingUnit {
+    pub archetype: String,
+    pub offset: f32,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct WavePlan {
+    #[serde(default, rename = "defender")]
+    pub defenders: Vec<StartingUnit>,
+    #[serde(default, rename = "wave")]
+    pub waves: Vec<WaveDefinition>,
+}
+
+impl WavePlan {
+
+    pub fn load(assets: &AssetLocator, name: &str, units: &UnitRegistry) -> Result<WavePlan, DataError> {
+        let path = Path::new(name);
+        let text = super::archetype::read_text(assets, name)?;
+        let plan: WavePlan = super::toml::from_str(&text).map_err(|e| DataError::Parse(path.to_path_buf(), e))?;
+        plan.validate(path, units)?;
+        Ok(plan)
+    }
+
+    // Every archetype named by a wave must exist and fight for the enemy team, every
+    // starting defender for the player. `path` is where the plan came from.
+    pub fn validate(&self, path: &Path, units: &UnitRegistry) -> Result<(), DataError> {
+        self.validate_waves(path, units)?;
+        for defender in &self.defenders {
+            if units.get(&defender.archetype)?.team != Team::Player {
+                return Err(DataError::NotAPlayerUnit(path.to_path_buf(), defender.archetype.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    // Just the waves, for a battle whose defenders are already standing.
+    pub fn validate_waves(&self, path: &Path, units: &UnitRegistry) -> Result<(), DataError> {
+        for group in self.waves.iter().flat_map(|w| &w.groups) {
+            if units.get(&group.archetype)?.team != Team::Enemy {
+                return Err(DataError::NotAnEnemy(path.to_path_buf(), group.archetype.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum WaveEvent {
+    Spawn { archetype: String, side: EnemyComing },
+    Cleared { wave: u32 },
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum WavePhase {
+    Delay,
+    Spawning,
+    Fighting,
+    Done,
+}
+
+
+// Walks through the plan one wave at a time: wait out the delay, spawn the
+// wave's enemies one by one, then wait for all of them to die.
+pub struct WaveSpawner {
+    plan: WavePlan,
+    current: usize,
+    phase: WavePhase,
+    timer: Timer,
+    queue: VecDeque<(String, EnemyComing)>,
+}
+
+impl WaveSpawner {
+
+    pub fn new(plan: WavePlan) -> WaveSpawner {
+        let phase = if plan.waves.is_empty() { WavePhase::Done } else { WavePhase::Delay };
+        WaveSpawner { plan, current: 0, phase, timer: Timer::new(), queue: VecDeque::new() }
+    }
+
+    // 1-based number of the wave in progress, or of the last one once all are done.
+    pub fn wave(&self) -> u32 {
+        (self.current.min(self.plan.waves.len().saturating_sub(1)) + 1) as u32
+    }
+
+    pub fn plan(&self) -> &WavePlan { &self.plan }
+
+    pub fn wave_count(&self) -> u32 {
+        self.plan.waves.len() as u32
+    }
+
+    // Seconds until the next wave starts spawning, while waiting for it.
+    pub fn countdown(&self) -> Option<f32> {
+        match self.phase {
+            WavePhase::Delay => Some((self.plan.waves[self.current].delay - self.timer.elapsed_seconds()).max(0.0)),
+            _ => None,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.phase == WavePhase::Done
+    }
+
+    pub fn update(&mut self, dt: f32, enemies_alive: bool) -> Vec<WaveEvent> {
+        let mut events = Vec::new();
+        self.timer.tick(dt);
+        match self.phase {
+            WavePhase::Delay => {
+                let wave = &self.plan.waves[self.current];
+                if self.timer.elapsed_seconds() >= wave.delay {
+                    self.queue = wave.groups.iter()
+                        .flat_map(|g| (0..g.count).map(move |_| (g.archetype.clone(), g.side)))
+                        .collect();
+                    // The first enemy shows up on the next tick rather than a full interval later.
+                    self.timer.restart();
+                    self.timer.tick(wave.interval);
+                    self.phase = WavePhase::Spawning;
+                }
+            },
+            WavePhase::Spawning => {
+                if self.timer.elapsed_seconds() >= self.plan.waves[self.current].interval {
+                    self.timer.restart();
+                    if let Some((archetype, side)) = self.queue.pop_front() {
+                        events.push(WaveEvent::Spawn { archetype, side });
+                    }
+                }
+                if self.queue.is_empty() {
+                    self.phase = WavePhase::Fighting;
+                }
+            },
+            WavePhase::Fighting => {
+                if !enemies_alive {
+                    events.push(WaveEvent::Cleared { wave: self.wave() });
+                    self.current += 1;
+                    self.timer.restart();
+                    self.phase = if self.current < self.plan.waves.len() { WavePhase::Delay } else { WavePhase::Done };
+                }
+            },
+            WavePhase::Done => {},
+        }
+        events
+    }
+}
@@ -0,0 +1,540 @@
+use super::entity::{self, Damageable, Combatant, Entity, BuildingType, Footprint};
+use super::ui::GeoInfo;
+use super::sfml::graphics::Transformable;
+use super::sfml::system::Vector2f;
+use super::EnemyComing;
+use super::handle::{Handle, IdAllocator, Team};
+use super::events::{CombatEvent, EventBus};
+use super::archetype::{UnitArchetype, UnitRegistry, DataError};
+use super::state_machine::StateContext;
+use super::waves::{WavePlan, WaveSpawner, WaveEvent};
+use super::cards::{CardEffect, CardLibrary, Cards, PlayError};
+use super::texture_manager::TextureManager;
+use crate::settings::{Settings, BoundaryPolicy};
+use super::rand::SeedableRng;
+use super::rand::rngs::StdRng;
+use std::collections::BTreeMap;
+use std::fmt;
+
+
+// Looks up an archetype with the difficulty applied to enemies.
+fn scaled_archetype(units: &UnitRegistry, settings: &Settings, name: &str) -> Result<UnitArchetype, DataError> {
+    let mut archetype = units.get(name)?.clone();
+    if archetype.team == Team::Enemy {
+        let factor = settings.difficulty.enemy_factor();
+        archetype.stats.max_hp *= factor;
+        archetype.stats.attack_damage *= factor;
+    }
+    Ok(archetype)
+}
+
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PlacementError {
+    OutOfBounds,
+    TooClose(BuildingType),
+}
+
+impl fmt::Display for PlacementError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlacementError::OutOfBounds => write!(f, "buildings have to stand inside the world"),
+            PlacementError::TooClose(BuildingType::Base) => write!(f, "too close to the base"),
+            PlacementError::TooClose(BuildingType::Others) => write!(f, "too close to another building"),
+        }
+    }
+}
+
+
+struct AttackInfo {
+    attacker: Handle,
+    target: Handle,
+    dmg: f32,
+}
+
+
+pub struct World<'a> {
+    humans: Vec<entity::Human<'a>>,
+    humans_pos_list: BTreeMap<Handle, Vector2f>,
+    enemies: Vec<entity::Enemy<'a>>,
+    enemies_pos_list: BTreeMap<Handle, Vector2f>,
+    buildings: Vec<entity::Building<'a>>,
+    buildings_pos_list: BTreeMap<Handle, Footprint>,
+    attacks: Vec<AttackInfo>,
+    events: EventBus<'a>,
+    waves: WaveSpawner,
+    cards: Cards,
+    lives: u32,
+    gold: f32,
+    is_game_over: bool,
+    is_victory: bool,
+    rng: StdRng,
+    ids: IdAllocator,
+    units: UnitRegistry,
+    settings: Settings,
+    textures: &'a TextureManager,
+}
+
+impl<'a> World<'a> {
+
+    // `deck` is the list of cards the battle starts with, drawn from `cards`. Fails if
+    // a starting defender names an archetype `units` does not have.
+    pub fn new(settings: &Settings, units: UnitRegistry, waves: WavePlan, cards: CardLibrary, deck: &[String],
+               textures: &'a TextureManager) -> Result<World<'a>, DataError> {
+        let defenders = waves.defenders.clone();
+        let mut world = World {
+            humans: Vec::new(),
+            humans_pos_list: BTreeMap::new(),
+            enemies: Vec::new(),
+            enemies_pos_list: BTreeMap::new(),
+            buildings: Vec::new(),
+            buildings_pos_list: BTreeMap::new(),
+            attacks: Vec::new(),
+            events: EventBus::new(),
+            waves: WaveSpawner::new(waves),
+            // Seeded apart from the battle so drawing cards does not change how fights play out.
+            cards: Cards::new(cards, deck, settings.seed.wrapping_add(1)),
+            lives: settings.lives,
+            gold: settings.starting_gold,
+            is_game_over: false,
+            is_victory: false,
+            rng: StdRng::seed_from_u64(settings.seed),
+            ids: IdAllocator::new(),
+            units,
+            settings: settings.clone(),
+            textures,
+        };
+        let center = settings.world_width / 2.0;
+        world.spawn_building(BuildingType::Base, center);
+        for defender in &defenders {
+            world.spawn_unit(&defender.archetype, Some(center + defender.offset))?;
+        }
+        Ok(world)
+    }
+
+    // The archetype's team decides which side the unit fights for; `x` overrides
+    // the default spawn point. An enemy placed left of the centre advances to the right.
+    pub fn spawn_unit(&mut self, name: &str, x: Option<f32>) -> Result<Handle, DataError> {
+        let side = match x {
+            Some(x) if x < self.settings.world_width / 2.0 => EnemyComing::LEFT,
+            _ => EnemyComing::RIGHT,
+        };
+        self.spawn(name, x, side)
+    }
+
+    // Enemies are scaled by the difficulty and enter from beyond the `side` edge
+    // unless `x` says otherwise.
+    fn spawn(&mut self, name: &str, x: Option<f32>, side: EnemyComing) -> Result<Handle, DataError> {
+        let archetype = scaled_archetype(&self.units, &self.settings, name)?;
+        let handle = self.ids.unit(archetype.team);
+        let ground_y = self.settings.ground_pos_y;
+        match archetype.team {
+            Team::Player => {
+                let mut human = entity::Human::new(handle, name, &archetype, &self.settings, self.textures);
+                if let Some(x) = x {
+                    human.image.set_position(Vector2f::new(x, ground_y));
+                }
+                self.humans_pos_list.insert(handle, human.get_position());
+                self.humans.push(human);
+            },
+            Team::Enemy => {
+                let mut enemy = entity::Enemy::new(handle, name, &archetype, &self.settings, side, self.textures);
+                if let Some(x) = x {
+                    enemy.image.set_position(Vector2f::new(x, ground_y));
+                }
+                self.enemies_pos_list.insert(handle, enemy.get_position());
+                self.enemies.push(enemy);
+            },
+        }
+        Ok(handle)
+    }
+
+    // Swaps in reloaded settings and unit data mid-battle. Units whose archetype
+    // no longer exists keep what they had.
+    pub fn apply_settings(&mut self, settings: &Settings, units: UnitRegistry, cards: CardLibrary) {
+        self.settings = settings.clone();
+        self.units = units;
+        self.cards.set_library(cards);
+
+        for human in &mut self.humans {
+            if let Ok(archetype) = scaled_archetype(&self.units, &self.settings, human.archetype()) {
+                human.apply_archetype(&archetype, &self.settings, self.textures);
+                self.humans_pos_list.insert(human.get_handle(), human.get_position());
+            }
+        }
+        for enemy in &mut self.enemies {
+            if let Ok(archetype) = scaled_archetype(&self.units, &self.settings, enemy.archetype()) {
+                enemy.apply_archetype(&archetype, &self.settings, self.textures);
+                self.enemies_pos_list.insert(enemy.get_handle(), enemy.get_position());
+            }
+        }
+        for building in &mut self.buildings {
+            building.apply_settings(&self.settings);
+            self.buildings_pos_list.insert(building.get_handle(), building.footprint());
+        }
+    }
+
+    // A new building has to stand inside the world and leave `building_spacing`
+    // of empty ground to every building already there.
+    pub fn check_building_placement(&self, building_type: BuildingType, x: f32) -> Result<(), PlacementError> {
+        let footprint = Footprint { pos: Vector2f::new(x, self.settings.ground_pos_y),
+                                    size: building_type.size(&self.settings) };
+        let half_width = footprint.size.x / 2.0;
+        if x - half_width < 0.0 || x + half_width > self.settings.world_width {
+            return Err(PlacementError::OutOfBounds);
+        }
+        for (handle, other) in &self.buildings_pos_list {
+            if footprint.gap(other) < self.settings.building_spacing {
+                let other_type = self.buildings.iter().find(|b| b.get_handle() == *handle)
+                    .map_or(BuildingType::Others, |b| b.building_type);
+                return Err(PlacementError::TooClose(other_type));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn spawn_building(&mut self, building_type: BuildingType, x: f32) -> Handle {
+        let mut building = entity::Building::new(building_type, self.ids.building(Team::Player), &self.settings,
+                                               self.textures);
+        building.set_position(Vector2f::new(x, self.settings.ground_pos_y));
+        let handle = building.get_handle();
+        self.buildings_pos_list.insert(handle, building.footprint());
+        self.buildings.push(building);
+        handle
+    }
+
+    // Pays for the card in hand at `index` and plays it at ground position `x`.
+    pub fn play_card(&mut self, index: usize, x: f32) -> Result<(), PlayError> {
+        let card = self.cards.hand_card(index).ok_or(PlayError::NoCard(index))?.clone();
+        if card.cost > self.gold {
+            return Err(PlayError::NotEnoughGold { cost: card.cost, gold: self.gold });
+        }
+        let x = x.clamp(0.0, self.settings.world_width);
+        match &card.effect {
+            CardEffect::Spawn { archetype } => {
+                self.spawn_unit(archetype, Some(x)).map_err(PlayError::Data)?;
+            },
+            CardEffect::Build { building } => {
+                self.check_building_placement(*building, x).map_err(PlayError::Placement)?;
+                self.spawn_building(*building, x);
+            },
+            CardEffect::Fireball { damage, radius } => {
+                let source = self.ids.spell(Team::Player);
+                // Nothing dead should be left in the list here; the HP check only keeps a corpse from taking hits.
+                let targets = self.enemies.iter_mut().filter(|e| e.get_hp() > 0.0 && (e.get_position().x - x).abs() <= *radius);
+                for enemy in targets {
+                    let amount = enemy.take_damage(*damage);
+                    self.events.emit(CombatEvent::DamageDealt { source, target: enemy.get_handle(), amount });
+                }
+            },
+            CardEffect::Heal { amount, radius } => {
+                for human in self.humans.iter_mut().filter(|h| (h.get_position().x - x).abs() <= *radius) {
+                    human.set_hp(human.get_hp() + amount);
+                }
+            },
+        }
+        self.gold -= card.cost;
+        self.cards.discard(index);
+        // What the card killed is gone before anything else gets to act, and its
+        // bounty is paid straight away.
+        self.remove_dead();
+        Ok(())
+    }
+
+    pub fn cards(&self) -> &Cards { &self.cards }
+    pub fn humans(&self) -> &[entity::Human<'a>] { &self.humans }
+    pub fn enemies(&self) -> &[entity::Enemy<'a>] { &self.enemies }
+    pub fn buildings(&self) -> &[entity::Building<'a>] { &self.buildings }
+    pub fn base(&self) -> Option<&entity::Building<'a>> {
+        self.buildings.iter().find(|b| b.building_type == BuildingType::Base)
+    }
+
+    pub fn wave(&self) -> u32 { self.waves.wave() }
+    pub fn wave_count(&self) -> u32 { self.waves.wave_count() }
+    pub fn wave_plan(&self) -> &WavePlan { self.waves.plan() }
+    pub fn wave_countdown(&self) -> Option<f32> { self.waves.countdown() }
+    pub fn lives(&self) -> u32 { self.lives }
+    pub fn gold(&self) -> f32 { self.gold }
+    pub fn is_game_over(&self) -> bool { self.is_game_over }
+    pub fn is_victory(&self) -> bool { self.is_victory }
+
+    pub fn subscribe<F: FnMut(&CombatEvent) + 'a>(&mut self, subscriber: F) {
+        self.events.subscribe(subscriber);
+    }
+
+    // A tick runs in four phases: the wave spawner adds enemies, every unit acts,
+    // the attacks they queued are resolved, then the events raised along the way
+    // are published.
+    pub fn step(&mut self, dt: f32) {
+        if self.is_game_over || self.is_victory {
+            return;
+        }
+
+        self.spawn_waves(dt);
+        self.gold += self.settings.gold_per_second * dt;
+        self.cards.update(dt);
+
+        for enemy in &mut self.enemies {
+            let mut ctx = StateContext { dt, rng: &mut self.rng, settings: &self.settings };
+            enemy.update_building_pos_list(&self.buildings_pos_list);
+            enemy.update(&mut ctx, &mut self.enemies_pos_list, &self.humans_pos_list);
+            if let Some(target) = enemy.get_swing_target() {
+                self.events.emit(CombatEvent::AttackStarted { attacker: enemy.get_handle(), target });
+            }
+            if let Some((target, dmg)) = enemy.get_attack_target() {
+                self.attacks.push(AttackInfo {attacker: enemy.get_handle(), target, dmg});
+            }
+        }
+
+        self.assign_flanks();
+        for human in &mut self.humans {
+            let mut ctx = StateContext { dt, rng: &mut self.rng, settings: &self.settings };
+            human.update(&mut ctx, &mut self.humans_pos_list, &self.enemies_pos_list);
+            if let Some(target) = human.get_swing_target() {
+                self.events.emit(CombatEvent::AttackStarted { attacker: human.get_handle(), target });
+            }
+            if let Some((target, dmg)) = human.get_attack_target() {
+                self.attacks.push(AttackInfo {attacker: human.get_handle(), target, dmg});
+            }
+        }
+
+        self.resolve_attacks();
+        self.remove_dead();
+        if self.settings.boundary_policy == BoundaryPolicy::Escape {
+            self.remove_escaped();
+        }
+
+        if self.enemies.is_empty() && self.waves.is_finished() && !self.is_game_over {
+            self.is_victory = true;
+        }
+
+        self.events.publish();
+    }
+
+    fn spawn_waves(&mut self, dt: f32) {
+        for event in self.waves.update(dt, !self.enemies.is_empty()) {
+            match event {
+                WaveEvent::Spawn { archetype, side } => {
+                    // Plans are checked against the registry when loaded and again on
+                    // every reload, so this only fails if that check was skipped.
+                    if let Err(err) = self.spawn(&archetype, None, side) {
+                        eprintln!("Error spawning a wave: {}", err);
+                    }
+                },
+                WaveEvent::Cleared { wave } => {
+                    self.cards.wave_cleared();
+                    self.events.emit(CombatEvent::WaveCleared { wave });
+                },
+            }
+        }
+    }
+
+    // Splits the defenders between the two flanks in proportion to the enemy HP
+    // on each side of the base. The units nearest a flank are the ones sent there,
+    // and any flank under attack gets at least one defender while there are two.
+    // Each one runs to the living enemy on its flank that is closest to it.
+    fn assign_flanks(&mut self) {
+        let center = self.base().map_or(self.settings.world_width / 2.0, |b| b.get_position().x);
+        let (mut left, mut right) = (0.0, 0.0);
+        for enemy in &self.enemies {
+            if enemy.get_position().x < center {
+                left += enemy.get_hp();
+            } else {
+                right += enemy.get_hp();
+            }
+        }
+
+        let count = self.humans.len();
+        let mut to_left = if left + right > 0.0 {
+            (count as f32 * left / (left + right)).round() as usize
+        } else {
+            0
+        };
+        if count > 1 {
+            if left > 0.0 && to_left == 0 {
+                to_left = 1;
+            }
+            if right > 0.0 && to_left == count {
+                to_left = count - 1;
+            }
+        }
+
+        let mut order: Vec<usize> = (0..count).collect();
+        order.sort_by(|&a, &b| self.humans[a].get_position().x.total_cmp(&self.humans[b].get_position().x)
+            .then_with(|| self.humans[a].get_handle().cmp(&self.humans[b].get_handle())));
+        for (rank, index) in order.into_iter().enumerate() {
+            let flank = if left + right <= 0.0 {
+                None
+            } else if rank < to_left {
+                Some(EnemyComing::LEFT)
+            } else {
+                Some(EnemyComing::RIGHT)
+            };
+            let x = self.humans[index].get_position().x;
+            let front = flank.map(|flank| {
+                self.enemies.iter()
+                    .filter(|e| e.get_hp() > 0.0 && (e.get_position().x < center) == (flank == EnemyComing::LEFT))
+                    .map(|e| e.get_position().x)
+                    .min_by(|a, b| (a - x).abs().total_cmp(&(b - x).abs()))
+                    .unwrap_or(if flank == EnemyComing::LEFT { 0.0 } else { self.settings.world_width })
+            });
+            self.humans[index].defend(front);
+        }
+    }
+
+    fn resolve_attacks(&mut self) {
+        for attack in self.attacks.drain(..) {
+            let target: Option<&mut dyn Damageable> = match attack.target.team() {
+                Team::Player => match self.humans.iter_mut().find(|h| h.get_handle() == attack.target) {
+                    Some(human) => Some(human),
+                    None => self.buildings.iter_mut().find(|b| b.get_handle() == attack.target)
+                        .map(|b| b as &mut dyn Damageable),
+                },
+                Team::Enemy => self.enemies.iter_mut().find(|e| e.get_handle() == attack.target)
+                    .map(|e| e as &mut dyn Damageable),
+            };
+            if let Some(target) = target {
+                if target.get_hp() <= 0.0 {
+                    continue;
+                }
+                let amount = target.take_damage(attack.dmg);
+                self.events.emit(CombatEvent::DamageDealt {
+                    source: attack.attacker,
+                    target: attack.target,
+                    amount,
+                });
+            }
+        }
+    }
+
+    // Units that walked off the map leave the battle. Every enemy that gets away
+    // costs a life, and running out of lives loses the battle like losing the base.
+    fn remove_escaped(&mut self) {
+        let world_width = self.settings.world_width;
+        for human in self.humans.iter().filter(|h| h.is_off_map(world_width)) {
+            self.humans_pos_list.remove(&human.get_handle());
+            self.events.emit(CombatEvent::UnitEscaped { handle: human.get_handle(), lives_left: self.lives });
+        }
+        self.humans.retain(|h| !h.is_off_map(world_width));
+
+        for enemy in self.enemies.iter().filter(|e| e.is_off_map(world_width)) {
+            self.lives = self.lives.saturating_sub(1);
+            self.enemies_pos_list.remove(&enemy.get_handle());
+            self.events.emit(CombatEvent::UnitEscaped { handle: enemy.get_handle(), lives_left: self.lives });
+        }
+        self.enemies.retain(|e| !e.is_off_map(world_width));
+
+        if self.lives == 0 {
+            self.is_game_over = true;
+        }
+    }
+
+    fn remove_dead(&mut self) {
+        for human in self.humans.iter().filter(|h| h.get_hp() <= 0.0) {
+            self.humans_pos_list.remove(&human.get_handle());
+            self.events.emit(CombatEvent::EntityDied { handle: human.get_handle() });
+        }
+        self.humans.retain(|h| h.get_hp() > 0.0);
+
+        for enemy in self.enemies.iter().filter(|e| e.get_hp() <= 0.0) {
+            self.gold += self.units.get(enemy.archetype()).map_or(0.0, |a| a.bounty);
+            self.enemies_pos_list.remove(&enemy.get_handle());
+            self.events.emit(CombatEvent::EntityDied { handle: enemy.get_handle() });
+        }
+        self.enemies.retain(|e| e.get_hp() > 0.0);
+
+        for building in self.buildings.iter().filter(|b| b.get_hp() <= 0.0) {
+            self.buildings_pos_list.remove(&building.get_handle());
+            self.events.emit(CombatEvent::BuildingDestroyed {
+                handle: building.get_handle(),
+                building_type: building.building_type,
+            });
+            if building.building_type == BuildingType::Base {
+                self.is_game_over = true;
+            }
+        }
+        self.buildings.retain(|b| b.get_hp() > 0.0);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::assets::AssetLocator;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // A battle on the shipped data files, without a window or any textures.
+    fn world<'a>(settings: &Settings, textures: &'a TextureManager) -> World<'a> {
+        let assets = AssetLocator::new(settings).unwrap();
+        let units = UnitRegistry::load(&assets, &settings.units_data_dir).unwrap();
+        let waves = WavePlan::load(&assets, &settings.waves_file, &units).unwrap();
+        let cards = CardLibrary::load(&assets, &settings.cards_file, &units).unwrap();
+        let deck = cards.deck.clone();
+        World::new(settings, units, waves, cards, &deck, textures).unwrap()
+    }
+
+    fn run(world: &mut World, settings: &Settings, seconds: f32) {
+        for _ in 0..(seconds / settings.fixed_timestep) as usize {
+            world.step(settings.fixed_timestep);
+        }
+    }
+
+    #[test]
+    fn steps_without_a_window() {
+        let settings = Settings::default();
+        let textures = TextureManager::new();
+        let mut world = world(&settings, &textures);
+        assert!(world.base().is_some());
+        assert_eq!(world.humans().len(), world.wave_plan().defenders.len());
+        assert!(world.enemies().is_empty());
+        assert!(!world.cards().hand().is_empty());
+
+        // The first wave starts spawning after its delay.
+        let delay = world.wave_plan().waves[0].delay;
+        run(&mut world, &settings, delay + 1.0);
+        assert!(!world.enemies().is_empty());
+        assert_eq!(world.wave(), 1);
+        assert!(world.gold() > settings.starting_gold);
+        assert!(!world.is_game_over() && !world.is_victory());
+        assert!(world.base().is_some());
+        for human in world.humans() {
+            let x = human.get_position().x;
+            assert!(x >= 0.0 && x <= settings.world_width);
+        }
+    }
+
+    // Everything a replay has to reproduce: the events published so far and where
+    // every unit stands with how much HP.
+    #[derive(Debug, PartialEq)]
+    struct Replay {
+        events: Vec<CombatEvent>,
+        units: Vec<(Handle, Vector2f, f32)>,
+        gold: f32,
+        lives: u32,
+    }
+
+    fn play(settings: &Settings, seconds: f32) -> Replay {
+        let textures = TextureManager::new();
+        let mut world = world(settings, &textures);
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let recorder = Rc::clone(&events);
+        world.subscribe(move |event| recorder.borrow_mut().push(*event));
+        run(&mut world, settings, seconds);
+
+        let humans = world.humans().iter().map(|h| (h.get_handle(), h.get_position(), h.get_hp()));
+        let enemies = world.enemies().iter().map(|e| (e.get_handle(), e.get_position(), e.get_hp()));
+        let units = humans.chain(enemies).collect();
+        let events = events.borrow().clone();
+        Replay { events, units, gold: world.gold(), lives: world.lives() }
+    }
+
+    #[test]
+    fn same_seed_same_battle() {
+        let settings = Settings::default();
+        let first = play(&settings, 30.0);
+        assert!(first.events.iter().any(|e| matches!(e, CombatEvent::DamageDealt { .. })));
+        assert_eq!(first, play(&settings, 30.0));
+    }
+}
@@ -1,8 +1,24 @@
 mod settings;
 mod game;
 
+use std::fmt::Display;
+use std::process;
+
 fn main() {
-    let mut main_game = game::Game::new(settings::WINDOW_WIDTH,
-                                        settings::WINDOW_HEIGHT, settings::TITLE);
+    let cli = settings::CommandLine::parse(std::env::args().skip(1)).unwrap_or_else(|err| exit_with(err));
+    let settings = settings::Settings::load(&cli).unwrap_or_else(|err| exit_with(err));
+    let assets = game::AssetLocator::new(&settings).unwrap_or_else(|err| exit_with(err));
+    if settings.headless {
+        game::run_headless(&settings, &assets).unwrap_or_else(|err| exit_with(err));
+        return;
+    }
+    let textures = game::TextureManager::load(&assets, &settings.textures_dir);
+    let fonts = game::FontManager::load(&assets, &settings.fonts_dir);
+    let mut main_game = game::Game::new(settings, cli, &assets, &textures, &fonts).unwrap_or_else(|err| exit_with(err));
     main_game.run();
 }
+
+fn exit_with<E: Display>(err: E) -> ! {
+    eprintln!("{}", err);
+    process::exit(2);
+}
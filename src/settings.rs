@@ -1,22 +1,280 @@
-// Window Settings
-pub static WINDOW_WIDTH: u32 = 1280;
-pub static WINDOW_HEIGHT: u32 = 720;
-pub static TITLE: &str = "Castle";
-
-// World Settings
-pub static GROUND_HEIGHT: f32 = 35.0;
-pub static GROUND_POS_Y: f32 = 550.0;
-
-// Humans Settings
-pub static HUMANS_MAX_HP: f32 = 100.0;
-pub static HUMANS_HEIGHT: f32 = 50.0;
-pub static HUMANS_POS_Y: f32 = 525.0;
-pub static HUMANS_WALK_SPEED: f32 = 1.5;
-pub static HUMANS_IDLE_WALK_SPEED_FACTOR: f32 = 0.2;
-
-// Enemies Settings
-pub static ENEMY_WALK_SPEED: f32 = 1.0;
-
-// Buildings settings
-pub static BUILDING_BASE_MAX_HP: f32 = 500.0;
-pub static BUILDING_OTHERS_MAX_HP: f32 = 300.0;
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+
+pub static CONFIG_PATH: &str = "config.toml";
+
+pub static USAGE: &str = "usage: castle [--config PATH] [--width N] [--height N] [--seed N] \
+                          [--difficulty easy|normal|hard] [--headless]";
+
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+
+    // Scales the max HP and damage of every enemy spawned.
+    pub fn enemy_factor(self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.75,
+            Difficulty::Normal => 1.0,
+            Difficulty::Hard => 1.5,
+        }
+    }
+}
+
+
+// What happens to a unit that walks off either end of the world.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BoundaryPolicy {
+    // Reappear at the opposite edge.
+    Wrap,
+    // Stop at the edge.
+    Clamp,
+    // Leave the battle; an enemy that gets away costs a life.
+    Escape,
+}
+
+
+// Every field has a default, so a config file only needs the values it changes.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    // Window Settings
+    pub width: u32,
+    pub height: u32,
+    pub title: String,
+    pub headless: bool,
+
+    // Asset Settings
+    // Searched in order, each from the working directory and then next to the
+    // executable, before the archive and any assets embedded in the binary.
+    pub asset_roots: Vec<String>,
+    pub asset_archive: String,
+    // The rest name directories and files inside the asset roots.
+    pub textures_dir: String,
+    pub fonts_dir: String,
+    pub ui_font: String,
+
+    // Simulation Settings
+    pub fixed_timestep: f32,
+    pub max_frame_time: f32,
+    pub seed: u64,
+    pub difficulty: Difficulty,
+    pub game_speeds: Vec<f32>,
+    pub default_game_speed_index: usize,
+    // Game time after which a headless battle is called off.
+    pub headless_time_limit: f32,
+
+    // World Settings
+    pub world_width: f32,
+    pub boundary_policy: BoundaryPolicy,
+    pub lives: u32,
+    pub starting_gold: f32,
+    pub gold_per_second: f32,
+    pub ground_height: f32,
+    pub ground_pos_y: f32,
+
+    // Camera Settings
+    pub camera_pan_speed: f32,
+    // How close to the window edge the mouse has to be to scroll.
+    pub camera_edge_margin: f32,
+
+    // Units Settings
+    // Data files are looked up in the asset roots and archive like any other asset.
+    pub units_data_dir: String,
+    pub waves_file: String,
+    pub cards_file: String,
+    // Unlocked cards and deck presets; written by the game, created on first save.
+    pub profile_file: String,
+    pub humans_idle_walk_speed_factor: f32,
+
+    // Buildings Settings
+    pub building_base_max_hp: f32,
+    pub building_others_max_hp: f32,
+    pub building_base_armor: f32,
+    pub building_others_armor: f32,
+    // Width and height.
+    pub building_base_size: [f32; 2],
+    pub building_others_size: [f32; 2],
+    // Least empty ground a new building has to leave to the ones already standing.
+    pub building_spacing: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            width: 1280,
+            height: 720,
+            title: "Castle".to_string(),
+            headless: false,
+
+            asset_roots: vec!["src/res".to_string(), "res".to_string(), ".".to_string()],
+            asset_archive: "assets.pak".to_string(),
+            textures_dir: "textures".to_string(),
+            fonts_dir: "fonts".to_string(),
+            ui_font: "SourceCodePro.ttf".to_string(),
+
+            fixed_timestep: 1.0 / 60.0,
+            max_frame_time: 0.25,
+            seed: 20200601,
+            difficulty: Difficulty::Normal,
+            game_speeds: vec![0.25, 0.5, 1.0, 2.0, 4.0],
+            default_game_speed_index: 2,
+            headless_time_limit: 600.0,
+
+            world_width: 3200.0,
+            boundary_policy: BoundaryPolicy::Clamp,
+            lives: 10,
+            starting_gold: 100.0,
+            gold_per_second: 2.0,
+            ground_height: 35.0,
+            ground_pos_y: 550.0,
+
+            camera_pan_speed: 900.0,
+            camera_edge_margin: 16.0,
+
+            units_data_dir: "data/units".to_string(),
+            waves_file: "data/waves.toml".to_string(),
+            cards_file: "data/cards.toml".to_string(),
+            profile_file: "profile.toml".to_string(),
+            humans_idle_walk_speed_factor: 0.2,
+
+            building_base_max_hp: 500.0,
+            building_others_max_hp: 300.0,
+            building_base_armor: 10.0,
+            building_others_armor: 10.0,
+            building_base_size: [100.0, 100.0],
+            building_others_size: [70.0, 90.0],
+            building_spacing: 20.0,
+        }
+    }
+}
+
+impl Settings {
+
+    // Reads the config file named on the command line, or `CONFIG_PATH` if it
+    // exists, then applies the command line overrides on top.
+    pub fn load(cli: &CommandLine) -> Result<Settings, SettingsError> {
+        let mut settings = match &cli.config {
+            Some(path) => Settings::from_file(path)?,
+            None if Path::new(CONFIG_PATH).exists() => Settings::from_file(Path::new(CONFIG_PATH))?,
+            None => Settings::default(),
+        };
+        cli.apply(&mut settings);
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Settings, SettingsError> {
+        let text = fs::read_to_string(path).map_err(|e| SettingsError::Io(path.to_path_buf(), e))?;
+        toml::from_str(&text).map_err(|e| SettingsError::Parse(path.to_path_buf(), e))
+    }
+
+    fn validate(&self) -> Result<(), SettingsError> {
+        if self.width == 0 || self.height == 0 {
+            return Err(SettingsError::Invalid("window size must not be zero".to_string()));
+        }
+        if self.world_width <= 0.0 {
+            return Err(SettingsError::Invalid("world_width must be positive".to_string()));
+        }
+        if self.fixed_timestep <= 0.0 {
+            return Err(SettingsError::Invalid("fixed_timestep must be positive".to_string()));
+        }
+        if self.default_game_speed_index >= self.game_speeds.len() {
+            return Err(SettingsError::Invalid("default_game_speed_index is outside game_speeds".to_string()));
+        }
+        Ok(())
+    }
+}
+
+
+#[derive(Clone, Debug, Default)]
+pub struct CommandLine {
+    pub config: Option<PathBuf>,
+    width: Option<u32>,
+    height: Option<u32>,
+    seed: Option<u64>,
+    difficulty: Option<Difficulty>,
+    headless: bool,
+}
+
+impl CommandLine {
+
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<CommandLine, SettingsError> {
+        let mut cli = CommandLine::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| SettingsError::Args(format!("{} needs a value", arg)));
+            match arg.as_str() {
+                "--config" => cli.config = Some(PathBuf::from(value()?)),
+                "--width" => cli.width = Some(parse_value(&arg, &value()?)?),
+                "--height" => cli.height = Some(parse_value(&arg, &value()?)?),
+                "--seed" => cli.seed = Some(parse_value(&arg, &value()?)?),
+                "--difficulty" => {
+                    let difficulty = value()?;
+                    cli.difficulty = Some(match difficulty.as_str() {
+                        "easy" => Difficulty::Easy,
+                        "normal" => Difficulty::Normal,
+                        "hard" => Difficulty::Hard,
+                        _ => return Err(SettingsError::Args(format!("unknown difficulty `{}`", difficulty))),
+                    });
+                },
+                "--headless" => cli.headless = true,
+                _ => return Err(SettingsError::Args(format!("unknown argument `{}`", arg))),
+            }
+        }
+        Ok(cli)
+    }
+
+    fn apply(&self, settings: &mut Settings) {
+        if let Some(width) = self.width {
+            settings.width = width;
+        }
+        if let Some(height) = self.height {
+            settings.height = height;
+        }
+        if let Some(seed) = self.seed {
+            settings.seed = seed;
+        }
+        if let Some(difficulty) = self.difficulty {
+            settings.difficulty = difficulty;
+        }
+        if self.headless {
+            settings.headless = true;
+        }
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, SettingsError> {
+    value.parse().map_err(|_| SettingsError::Args(format!("invalid value `{}` for {}", value, flag)))
+}
+
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Args(String),
+    Invalid(String),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            SettingsError::Parse(path, err) => write!(f, "{}: {}", path.display(), err),
+            SettingsError::Args(msg) => write!(f, "{}\n{}", msg, USAGE),
+            SettingsError::Invalid(msg) => write!(f, "invalid settings: {}", msg),
+        }
+    }
+}